@@ -0,0 +1,60 @@
+//! The world point under the screen center crosshair, for weapons to aim at.
+use avian3d::prelude::*;
+use bevy::prelude::*;
+
+use crate::{
+    camera::{
+        WaltzCamera,
+        config::{CameraConfig, CollisionLayer},
+    },
+    character::WaltzPlayer,
+};
+
+/// What the crosshair points at, updated every tick from the steady camera, without the shake.
+#[derive(Resource, Debug, Clone, PartialEq, Reflect, Default)]
+#[reflect(Resource)]
+pub struct CrosshairTarget {
+    /// the start of the crosshair ray
+    pub origin: Vec3,
+    /// the hit point, or the end of the ray when nothing is hit
+    pub point: Vec3,
+    pub entity: Option<Entity>,
+    pub normal: Option<Vec3>,
+}
+
+pub(super) fn update_crosshair_target(
+    camera: Single<&Transform, With<WaltzCamera>>,
+    player: Option<Single<Entity, With<WaltzPlayer>>>,
+    spatial_query: SpatialQuery,
+    config: Res<CameraConfig>,
+    mut crosshair: ResMut<CrosshairTarget>,
+) {
+    let origin = camera.translation;
+    let direction = camera.forward();
+    let max_distance = config.aim.crosshair_distance;
+
+    // sensors like vines must not catch the crosshair
+    let mut filter = SpatialQueryFilter::from_mask([
+        CollisionLayer::Character,
+        CollisionLayer::Terrain,
+        CollisionLayer::CameraObstacle,
+    ]);
+    if let Some(player) = player {
+        filter = filter.with_excluded_entities([*player]);
+    }
+
+    *crosshair = match spatial_query.cast_ray(origin, direction, max_distance, true, &filter) {
+        Some(hit) => CrosshairTarget {
+            origin,
+            point: origin + direction * hit.distance,
+            entity: Some(hit.entity),
+            normal: Some(hit.normal),
+        },
+        None => CrosshairTarget {
+            origin,
+            point: origin + direction * max_distance,
+            entity: None,
+            normal: None,
+        },
+    };
+}
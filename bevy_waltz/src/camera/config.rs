@@ -2,6 +2,8 @@ use avian3d::prelude::*;
 use bevy::prelude::*;
 use serde::{Deserialize, Serialize};
 
+use crate::camera::IngameCameraKind;
+
 #[derive(PhysicsLayer, Debug, Default)]
 pub(crate) enum CollisionLayer {
     #[default]
@@ -9,17 +11,29 @@ pub(crate) enum CollisionLayer {
     Terrain,
     CameraObstacle,
     Sensor,
+    /// one-way platforms, the characters only see them through their ghost sensor
+    FallThrough,
 }
 
 #[derive(Resource, Clone, PartialEq, Reflect, Serialize, Deserialize)]
 #[reflect(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub(crate) struct CameraConfig {
     pub(crate) fixed_angle: FixedAngle,
     pub(crate) first_person: FirstPerson,
     pub(crate) third_person: ThirdPersion,
+    pub(crate) framing: Framing,
+    pub(crate) shake: Shake,
+    pub(crate) aim: Aim,
     pub(crate) mouse_sensitivity_x: f32,
     pub(crate) mouse_sensitivity_y: f32,
     pub(crate) decay_rate: f32,
+    /// vertical field of view in degrees outside of the aim mode
+    pub(crate) fov: f32,
+    /// seconds to blend the smoothing when switching between camera kinds
+    pub(crate) transition_duration: f32,
+    /// smoothing used at the start of a camera kind transition
+    pub(crate) transition_smoothing: f32,
 }
 
 impl Default for CameraConfig {
@@ -56,15 +70,133 @@ impl Default for CameraConfig {
                 zoom_in_smoothing: 0.2,
                 zoom_out_smoothing: 1.2,
             },
+            framing: Framing {
+                blend_duration: 0.8,
+                screen_offset_x: 0.0,
+                screen_offset_y: 0.1,
+                angle: 25.0,
+                pitch: -10.0,
+                padding: 0.8,
+                min_distance: 2.0,
+                max_distance: 12.0,
+            },
+            shake: Shake {
+                decay: 1.2,
+                frequency: 25.0,
+                max_offset: 0.15,
+                max_yaw: 2.0,
+                max_pitch: 2.0,
+                max_roll: 4.0,
+                knockback_weight: 0.8,
+                landing_weight: 0.5,
+                weapon_weight: 0.2,
+                hard_landing_speed: 12.0,
+            },
+            aim: Aim {
+                blend_duration: 0.2,
+                shoulder_swap_duration: 0.25,
+                shoulder_offset_x: 0.6,
+                shoulder_offset_y: 0.1,
+                distance: 1.4,
+                fov: 30.0,
+                crosshair_distance: 100.0,
+            },
             mouse_sensitivity_x: 8e-4,
             mouse_sensitivity_y: 5e-4,
             decay_rate: 50.0,
+            fov: 45.0,
+            transition_duration: 0.6,
+            transition_smoothing: 2.0,
+        }
+    }
+}
+
+/// The smoothing factors of a camera kind, a bigger value means a slower camera.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct CameraSmoothing {
+    pub(crate) translation: f32,
+    pub(crate) rotation: f32,
+}
+
+/// The zoom parameters of a camera kind that can zoom.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct CameraZoomSettings {
+    pub(crate) speed: f32,
+    pub(crate) in_smoothing: f32,
+    pub(crate) out_smoothing: f32,
+}
+
+impl CameraConfig {
+    /// The view pitch range of the camera kind in degrees, min equals max for a fixed pitch.
+    pub(crate) fn pitch_range(&self, kind: &IngameCameraKind) -> (f32, f32) {
+        match kind {
+            IngameCameraKind::ThirdPerson => {
+                (self.third_person.min_pitch, self.third_person.max_pitch)
+            }
+            IngameCameraKind::FirstPerson => {
+                (self.first_person.min_pitch, self.first_person.max_pitch)
+            }
+            IngameCameraKind::FixedAngle => (self.fixed_angle.pitch, self.fixed_angle.pitch),
+        }
+    }
+
+    /// The distance range between camera and anchor, the first person camera sits on the anchor.
+    pub(crate) fn distance_range(&self, kind: &IngameCameraKind) -> (f32, f32) {
+        match kind {
+            IngameCameraKind::ThirdPerson => (
+                self.third_person.min_distance,
+                self.third_person.max_distance,
+            ),
+            IngameCameraKind::FirstPerson => (0.0, 0.0),
+            IngameCameraKind::FixedAngle => {
+                (self.fixed_angle.min_distance, self.fixed_angle.max_distance)
+            }
+        }
+    }
+
+    /// The zoom parameters of the camera kind, `None` if the kind cannot zoom.
+    pub(crate) fn zoom(&self, kind: &IngameCameraKind) -> Option<CameraZoomSettings> {
+        match kind {
+            IngameCameraKind::ThirdPerson => Some(CameraZoomSettings {
+                speed: self.third_person.zoom_speed,
+                in_smoothing: self.third_person.zoom_in_smoothing,
+                out_smoothing: self.third_person.zoom_out_smoothing,
+            }),
+            IngameCameraKind::FirstPerson => None,
+            IngameCameraKind::FixedAngle => Some(CameraZoomSettings {
+                speed: self.fixed_angle.zoom_speed,
+                in_smoothing: self.fixed_angle.zoom_in_smoothing,
+                out_smoothing: self.fixed_angle.zoom_out_smoothing,
+            }),
+        }
+    }
+
+    pub(crate) fn smoothing(&self, kind: &IngameCameraKind) -> CameraSmoothing {
+        match kind {
+            IngameCameraKind::ThirdPerson => CameraSmoothing {
+                translation: self.third_person.translation_smoothing,
+                rotation: self.third_person.rotation_smoothing,
+            },
+            IngameCameraKind::FirstPerson => CameraSmoothing {
+                translation: self.first_person.translation_smoothing,
+                rotation: self.first_person.rotation_smoothing,
+            },
+            IngameCameraKind::FixedAngle => CameraSmoothing {
+                translation: self.fixed_angle.translation_smoothing,
+                rotation: self.fixed_angle.rotation_smoothing,
+            },
         }
     }
+
+    /// Converts a smoothing factor to the decay rate used by `smooth_nudge`.
+    pub(crate) fn decay_rate_for(&self, smoothing: f32) -> f32 {
+        self.decay_rate / smoothing.max(1e-3)
+    }
 }
 
 #[derive(Resource, Clone, PartialEq, Reflect, Serialize, Deserialize, Default)]
 #[reflect(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub(crate) struct FixedAngle {
     pub(crate) min_distance: f32,
     pub(crate) max_distance: f32,
@@ -78,6 +210,7 @@ pub(crate) struct FixedAngle {
 
 #[derive(Resource, Clone, PartialEq, Reflect, Serialize, Deserialize, Default)]
 #[reflect(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub(crate) struct ThirdPersion {
     pub(crate) translation_smoothing: f32,
     pub(crate) rotation_smoothing: f32,
@@ -94,6 +227,7 @@ pub(crate) struct ThirdPersion {
 
 #[derive(Resource, Clone, PartialEq, Reflect, Serialize, Deserialize, Default)]
 #[reflect(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub(crate) struct FirstPerson {
     pub(crate) translation_smoothing: f32,
     pub(crate) rotation_smoothing: f32,
@@ -101,3 +235,60 @@ pub(crate) struct FirstPerson {
     pub(crate) min_pitch: f32,
     pub(crate) tracking_smoothing: f32,
 }
+
+/// Composition of the player and the secondary target, e.g. for a dialogue.
+#[derive(Resource, Clone, PartialEq, Reflect, Serialize, Deserialize, Default)]
+#[reflect(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct Framing {
+    /// seconds to blend in or out of the framing
+    pub(crate) blend_duration: f32,
+    /// where the middle of both subjects sits on the screen, in normalized device coordinates
+    pub(crate) screen_offset_x: f32,
+    pub(crate) screen_offset_y: f32,
+    /// yaw in degrees between the camera and the line from the target to the player
+    pub(crate) angle: f32,
+    pub(crate) pitch: f32,
+    /// extra space around both subjects
+    pub(crate) padding: f32,
+    pub(crate) min_distance: f32,
+    pub(crate) max_distance: f32,
+}
+
+/// Trauma based camera shake, angles are in degrees.
+#[derive(Resource, Clone, PartialEq, Reflect, Serialize, Deserialize, Default)]
+#[reflect(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct Shake {
+    /// trauma removed per second
+    pub(crate) decay: f32,
+    pub(crate) frequency: f32,
+    pub(crate) max_offset: f32,
+    pub(crate) max_yaw: f32,
+    pub(crate) max_pitch: f32,
+    pub(crate) max_roll: f32,
+    pub(crate) knockback_weight: f32,
+    pub(crate) landing_weight: f32,
+    pub(crate) weapon_weight: f32,
+    /// falling speed above which a landing shakes the camera
+    pub(crate) hard_landing_speed: f32,
+}
+
+/// Over the shoulder aim mode of the third person camera, the fov is in degrees.
+#[derive(Resource, Clone, PartialEq, Reflect, Serialize, Deserialize, Default)]
+#[reflect(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct Aim {
+    /// seconds to blend in or out of the aim mode
+    pub(crate) blend_duration: f32,
+    /// seconds to move the camera to the other shoulder
+    pub(crate) shoulder_swap_duration: f32,
+    /// offset of the pivot to the right shoulder, mirrored for the left one
+    pub(crate) shoulder_offset_x: f32,
+    pub(crate) shoulder_offset_y: f32,
+    /// distance between camera and shoulder pivot, replaces the zoom distance while aiming
+    pub(crate) distance: f32,
+    pub(crate) fov: f32,
+    /// max distance of the crosshair ray
+    pub(crate) crosshair_distance: f32,
+}
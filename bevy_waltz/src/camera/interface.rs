@@ -2,14 +2,26 @@
 use bevy::prelude::*;
 use std::f32::consts::PI;
 
-use crate::camera::{CameraOrbit, WaltzCamera};
+use crate::camera::{
+    CameraOrbit, CameraZoom, CameraZoomKind, FocusCamera, LockOnCamera, ReleaseCameraFocus,
+    ReleaseCameraLockOn, WaltzCamera, clamp_view_pitch, config::CameraConfig,
+};
 
 pub(super) fn orbit_rotation(
     mut commands: Commands,
     mut camera: Single<&mut WaltzCamera>,
     querys: Query<(Entity, &mut CameraOrbit)>,
+    config: Res<CameraConfig>,
 ) {
+    let (min_pitch, max_pitch) = config.pitch_range(&camera.kind);
+
     for (entity, orbit) in querys {
+        // the orbit is kept untouched while framing, so it can be restored afterwards
+        if camera.secondary_target.is_some() || camera.lock_on_target.is_some() {
+            commands.entity(entity).despawn();
+            continue;
+        }
+
         // pitch: rotation around the x-axis
         let right_vec = camera.direction.cross(Vec3::Y).normalize_or_zero();
         let pitch_quat = Quat::from_axis_angle(right_vec, -PI / 32.0 * orbit.pitch);
@@ -17,10 +29,85 @@ pub(super) fn orbit_rotation(
         // yaw: rotation around the y-axis
         let yaw_quat = Quat::from_rotation_y(PI / 32.0 * orbit.yaw);
 
-        camera.direction = pitch_quat * yaw_quat * camera.direction;
+        // keep the pitch inside the limits of the camera kind, so the direction never flips
+        camera.direction = clamp_view_pitch(
+            pitch_quat * yaw_quat * camera.direction,
+            min_pitch,
+            max_pitch,
+        );
 
         debug!("orbit camera new direction is {}", camera.direction);
 
         commands.entity(entity).despawn();
     }
 }
+
+/// Applies the zoom requests to the desired distance, then moves the current distance towards it.
+/// Zooming in uses `zoom_in_smoothing` and zooming out `zoom_out_smoothing`.
+pub(super) fn zoom_distance(
+    mut camera: Single<&mut WaltzCamera>,
+    mut zooms: MessageReader<CameraZoom>,
+    time: Res<Time>,
+    config: Res<CameraConfig>,
+) {
+    let (min_distance, max_distance) = config.distance_range(&camera.kind);
+
+    let Some(zoom) = config.zoom(&camera.kind) else {
+        zooms.clear();
+        camera.distance = min_distance;
+        return;
+    };
+
+    for CameraZoom { zoom: kind, amount } in zooms.read() {
+        let delta = zoom.speed * amount.abs();
+        camera.desired_distance += match kind {
+            CameraZoomKind::ZoomIn => -delta,
+            CameraZoomKind::ZoomOut => delta,
+        };
+        camera.desired_distance = camera.desired_distance.clamp(min_distance, max_distance);
+        debug!(
+            "camera zoom desired distance to {}",
+            camera.desired_distance
+        );
+    }
+
+    // the desired distance may be out of range right after switching the camera kind
+    let desired_distance = camera.desired_distance.clamp(min_distance, max_distance);
+    let smoothing = if desired_distance < camera.distance {
+        zoom.in_smoothing
+    } else {
+        zoom.out_smoothing
+    };
+
+    camera.distance.smooth_nudge(
+        &desired_distance,
+        config.decay_rate_for(smoothing),
+        time.delta_secs(),
+    );
+}
+
+pub(super) fn focus_camera(focus: On<FocusCamera>, mut camera: Single<&mut WaltzCamera>) {
+    debug!("camera focus on {:?}", focus.0);
+    camera.secondary_target = Some(focus.0);
+}
+
+pub(super) fn release_camera_focus(
+    _release: On<ReleaseCameraFocus>,
+    mut camera: Single<&mut WaltzCamera>,
+) {
+    debug!("camera focus released");
+    camera.secondary_target = None;
+}
+
+pub(super) fn lock_on_camera(lock_on: On<LockOnCamera>, mut camera: Single<&mut WaltzCamera>) {
+    debug!("camera lock-on on {:?}", lock_on.0);
+    camera.lock_on_target = Some(lock_on.0);
+}
+
+pub(super) fn release_camera_lock_on(
+    _release: On<ReleaseCameraLockOn>,
+    mut camera: Single<&mut WaltzCamera>,
+) {
+    debug!("camera lock-on released");
+    camera.lock_on_target = None;
+}
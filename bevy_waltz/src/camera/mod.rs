@@ -2,15 +2,33 @@ use bevy::prelude::*;
 use serde::{Deserialize, Serialize};
 
 use crate::{
-    camera::{config::CameraConfig, interface::orbit_rotation, system::follow_anchor},
+    camera::{
+        aim::update_crosshair_target,
+        config::CameraConfig,
+        interface::{
+            focus_camera, lock_on_camera, orbit_rotation, release_camera_focus,
+            release_camera_lock_on, zoom_distance,
+        },
+        shake::{
+            CameraShake, add_camera_trauma, apply_camera_shake, remove_camera_shake,
+            shake_on_knockback, shake_on_landing,
+        },
+        system::follow_anchor,
+    },
     character::WaltzPlayer,
+    utils::Vec3Ext,
 };
 
 pub(crate) mod config;
 
+mod aim;
 mod interface;
+mod shake;
 mod system;
 
+pub use aim::CrosshairTarget;
+pub use shake::{CameraShakeSource, ShakeCamera};
+
 #[derive(Debug, Clone, PartialEq, Reflect, Serialize, Deserialize, Default)]
 #[reflect(Serialize, Deserialize)]
 pub(crate) enum IngameCameraKind {
@@ -20,6 +38,16 @@ pub(crate) enum IngameCameraKind {
     FixedAngle,
 }
 
+impl IngameCameraKind {
+    pub(crate) fn next(&self) -> Self {
+        match self {
+            IngameCameraKind::ThirdPerson => IngameCameraKind::FirstPerson,
+            IngameCameraKind::FirstPerson => IngameCameraKind::FixedAngle,
+            IngameCameraKind::FixedAngle => IngameCameraKind::ThirdPerson,
+        }
+    }
+}
+
 #[derive(Debug, Copy, Clone, PartialEq, Reflect, Serialize, Deserialize)]
 #[reflect(Serialize, Deserialize)]
 pub(crate) enum CameraZoomKind {
@@ -27,10 +55,12 @@ pub(crate) enum CameraZoomKind {
     ZoomOut,
 }
 
-#[derive(Debug, Copy, Clone, PartialEq, Reflect, Component, Serialize, Deserialize)]
+/// Requests a zoom step, `amount` is usually the number of mouse wheel lines.
+#[derive(Debug, Copy, Clone, PartialEq, Reflect, Message, Serialize, Deserialize)]
 #[reflect(Serialize, Deserialize)]
 pub struct CameraZoom {
     pub zoom: CameraZoomKind,
+    pub amount: f32,
 }
 
 #[derive(Debug, Copy, Clone, PartialEq, Reflect, Component, Serialize, Deserialize)]
@@ -44,6 +74,34 @@ pub struct CameraOrbit {
 #[reflect(Component, Serialize, Deserialize)]
 pub struct WaltzCameraAnchor;
 
+/// Something the camera frames together with the player.
+#[derive(Debug, Copy, Clone, PartialEq, Reflect, Serialize, Deserialize)]
+#[reflect(Serialize, Deserialize)]
+pub enum CameraFocusTarget {
+    /// follows the entity while it moves
+    Entity(Entity),
+    /// follows a point offset from the entity origin, like its head
+    EntityOffset(Entity, Vec3),
+    Point(Vec3),
+}
+
+/// Blends the camera into a framing of the player and the target, e.g. when a dialogue starts.
+#[derive(Event, Debug, Copy, Clone, PartialEq)]
+pub struct FocusCamera(pub CameraFocusTarget);
+
+/// Blends the camera back to the orbit it had before [`FocusCamera`].
+#[derive(Event, Debug, Copy, Clone, PartialEq, Default)]
+pub struct ReleaseCameraFocus;
+
+/// Frames the lock-on target with the player, while no [`FocusCamera`] focus is set. The lock-on
+/// keeps its own target, so it neither replaces nor releases a dialogue focus.
+#[derive(Event, Debug, Copy, Clone, PartialEq)]
+pub(crate) struct LockOnCamera(pub CameraFocusTarget);
+
+/// Stops framing the lock-on target.
+#[derive(Event, Debug, Copy, Clone, PartialEq, Default)]
+pub(crate) struct ReleaseCameraLockOn;
+
 /// Rotation and distance are adjusted through the user interface,
 /// while translation by the system is influenced by anchor movement and collision.
 #[derive(Debug, Clone, PartialEq, Component, Reflect, Serialize, Deserialize)]
@@ -55,11 +113,31 @@ pub(crate) struct WaltzCamera {
     pub(crate) height: f32,
     /// disired distance between camera and anchor
     pub(crate) desired_distance: f32,
+    /// current distance between camera and anchor, follows the desired distance smoothly
+    pub(crate) distance: f32,
+    /// distance between camera and anchor after resolving the obstacles in between
+    pub(crate) collision_distance: f32,
     /// look_at parameter uses the camera's own reference frame
     pub(crate) target: Vec3,
-    /// for dialogue
-    pub(crate) secondary_target: Option<Vec3>,
+    /// for dialogue, framed together with the anchor while set
+    pub(crate) secondary_target: Option<CameraFocusTarget>,
+    /// for lock-on, framed while no secondary target is set
+    pub(crate) lock_on_target: Option<CameraFocusTarget>,
+    /// blend weight of the secondary target framing
+    pub(crate) framing: f32,
+    /// last known position of the secondary target, used while blending out
+    pub(crate) framing_point: Vec3,
     pub(crate) kind: IngameCameraKind,
+    /// progress of the transition to the current kind, 1.0 once finished
+    pub(crate) transition: f32,
+    /// over the shoulder aim mode requested
+    pub(crate) aiming: bool,
+    /// blend weight of the aim mode
+    pub(crate) aim: f32,
+    /// the shoulder the camera aims over, 1.0 for the right one and -1.0 for the left one
+    pub(crate) shoulder_side: f32,
+    /// current shoulder, moves towards `shoulder_side`
+    pub(crate) shoulder: f32,
 }
 
 impl Default for WaltzCamera {
@@ -69,17 +147,70 @@ impl Default for WaltzCamera {
             height: 0.0,
             target: Vec3::ZERO,
             secondary_target: None,
+            lock_on_target: None,
+            framing: 0.0,
+            framing_point: Vec3::ZERO,
             desired_distance: 1.0,
+            distance: 1.0,
+            collision_distance: 1.0,
             kind: IngameCameraKind::ThirdPerson,
+            transition: 1.0,
+            aiming: false,
+            aim: 0.0,
+            shoulder_side: 1.0,
+            shoulder: 1.0,
         }
     }
 }
 
+impl WaltzCamera {
+    /// Switches to the next camera kind and starts a smooth transition to it.
+    pub(crate) fn cycle_kind(&mut self) {
+        self.kind = self.kind.next();
+        self.transition = 0.0;
+    }
+
+    /// The aim mode is ignored by the fixed angle camera, it has no shoulder to aim over.
+    pub(crate) fn is_aiming(&self) -> bool {
+        self.aiming && self.kind != IngameCameraKind::FixedAngle
+    }
+
+    pub(crate) fn toggle_aim(&mut self) {
+        self.aiming = !self.aiming;
+    }
+
+    /// Moves the aim mode camera to the other shoulder.
+    pub(crate) fn swap_shoulder(&mut self) {
+        self.shoulder_side = -self.shoulder_side;
+    }
+}
+
+/// The pitch of the view in degrees, the camera looks in the opposite of the `direction`.
+pub(crate) fn view_pitch(direction: Vec3) -> f32 {
+    (-direction.normalize_or_zero().y)
+        .clamp(-1.0, 1.0)
+        .asin()
+        .to_degrees()
+}
+
+/// Clamps the view pitch of the `direction` to `min_pitch..=max_pitch` degrees, keeping its yaw.
+pub(crate) fn clamp_view_pitch(direction: Vec3, min_pitch: f32, max_pitch: f32) -> Vec3 {
+    let horizontal = direction.horizontal().normalize_or(Vec3::Z);
+    let pitch = view_pitch(direction)
+        .clamp(min_pitch, max_pitch)
+        .to_radians();
+
+    horizontal * pitch.cos() - Vec3::Y * pitch.sin()
+}
+
 fn setup_camera(mut commands: Commands) {
     commands.spawn((
         Name::new("waltz-camera"),
         Camera3d::default(),
         WaltzCamera::default(),
+        CameraShake::default(),
+        // the player sounds are spatial, the camera hears them
+        SpatialListener::new(0.3),
         Transform::from_xyz(0.0, 0.0, 0.0).looking_at(Vec3::ZERO, Vec3::Y),
     ));
 }
@@ -106,7 +237,29 @@ impl Plugin for WaltzCameraPlugin {
         app.register_type::<IngameCameraKind>()
             .register_type::<WaltzCamera>()
             .init_resource::<CameraConfig>()
+            .add_message::<CameraZoom>()
+            .add_observer(focus_camera)
+            .add_observer(release_camera_focus)
+            .add_observer(lock_on_camera)
+            .add_observer(release_camera_lock_on)
             .add_systems(Startup, setup_camera)
-            .add_systems(FixedUpdate, (follow_anchor, orbit_rotation));
+            .register_type::<CameraShake>()
+            .register_type::<CrosshairTarget>()
+            .init_resource::<CrosshairTarget>()
+            .add_observer(add_camera_trauma)
+            .add_systems(
+                FixedUpdate,
+                (
+                    orbit_rotation,
+                    zoom_distance,
+                    remove_camera_shake,
+                    follow_anchor,
+                    update_crosshair_target,
+                    apply_camera_shake,
+                )
+                    .chain(),
+            )
+            .add_observer(shake_on_landing)
+            .add_systems(FixedUpdate, shake_on_knockback);
     }
 }
@@ -0,0 +1,161 @@
+//! Trauma based camera shake.
+//!
+//! The shake is an offset on top of the transform computed by `follow_anchor`. It is removed
+//! before `follow_anchor` runs and applied again afterwards, so the smoothing always works on the
+//! steady camera and never tries to catch up with the noise.
+use bevy::prelude::*;
+use bevy_tnua::prelude::TnuaController;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    camera::{WaltzCamera, config::CameraConfig},
+    character::{
+        CharacterLanded, WaltzPlayer, WaltzTnuaCtrlScheme, WaltzTnuaCtrlSchemeActionDiscriminant,
+    },
+};
+
+/// What caused a shake, each source has its own weight in the config.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Reflect, Serialize, Deserialize)]
+#[reflect(Serialize, Deserialize)]
+pub enum CameraShakeSource {
+    Knockback,
+    Landing,
+    Weapon,
+    /// not weighted
+    Other,
+}
+
+/// Adds trauma to the camera, `trauma` is in `0.0..=1.0` before the source weight is applied.
+#[derive(Event, Debug, Copy, Clone, PartialEq)]
+pub struct ShakeCamera {
+    pub source: CameraShakeSource,
+    pub trauma: f32,
+}
+
+impl ShakeCamera {
+    pub fn new(source: CameraShakeSource, trauma: f32) -> Self {
+        Self { source, trauma }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Component, Reflect, Default)]
+#[reflect(Component)]
+pub(crate) struct CameraShake {
+    /// decays over time, the shake strength is the square of it
+    pub(crate) trauma: f32,
+    /// time driving the noise
+    elapsed: f32,
+    /// the offset applied on the last update
+    applied_translation: Vec3,
+    applied_rotation: Quat,
+}
+
+/// Smooth noise in `-1.0..=1.0`, each seed gives an independent channel.
+fn shake_noise(seed: f32, time: f32) -> f32 {
+    0.5 * (time + seed).sin()
+        + 0.3 * (2.3 * time + 1.7 * seed).sin()
+        + 0.2 * (4.1 * time + 2.9 * seed).sin()
+}
+
+pub(super) fn add_camera_trauma(
+    shake: On<ShakeCamera>,
+    mut camera_shake: Single<&mut CameraShake>,
+    config: Res<CameraConfig>,
+) {
+    let weight = match shake.source {
+        CameraShakeSource::Knockback => config.shake.knockback_weight,
+        CameraShakeSource::Landing => config.shake.landing_weight,
+        CameraShakeSource::Weapon => config.shake.weapon_weight,
+        CameraShakeSource::Other => 1.0,
+    };
+
+    camera_shake.trauma = (camera_shake.trauma + weight * shake.trauma).clamp(0.0, 1.0);
+    debug!(
+        "camera trauma {} from {:?}",
+        camera_shake.trauma, shake.source
+    );
+}
+
+pub(super) fn remove_camera_shake(
+    camera: Single<(&mut Transform, &mut CameraShake), With<WaltzCamera>>,
+) {
+    let (mut transform, mut shake) = camera.into_inner();
+
+    transform.translation -= shake.applied_translation;
+    transform.rotation = (transform.rotation * shake.applied_rotation.inverse()).normalize();
+
+    shake.applied_translation = Vec3::ZERO;
+    shake.applied_rotation = Quat::IDENTITY;
+}
+
+pub(super) fn apply_camera_shake(
+    camera: Single<(&mut Transform, &mut CameraShake), With<WaltzCamera>>,
+    config: Res<CameraConfig>,
+    time: Res<Time>,
+) {
+    let (mut transform, mut shake) = camera.into_inner();
+    let dt = time.delta_secs();
+
+    shake.trauma = (shake.trauma - config.shake.decay * dt).max(0.0);
+    if shake.trauma <= 0.0 {
+        return;
+    }
+
+    shake.elapsed += dt * config.shake.frequency;
+    let strength = shake.trauma * shake.trauma;
+    let time = shake.elapsed;
+
+    let translation = strength
+        * config.shake.max_offset
+        * Vec3::new(
+            shake_noise(1.0, time),
+            shake_noise(2.0, time),
+            shake_noise(3.0, time),
+        );
+    let rotation = Quat::from_euler(
+        EulerRot::YXZ,
+        strength * config.shake.max_yaw.to_radians() * shake_noise(4.0, time),
+        strength * config.shake.max_pitch.to_radians() * shake_noise(5.0, time),
+        strength * config.shake.max_roll.to_radians() * shake_noise(6.0, time),
+    );
+
+    // the translation noise is in the camera space
+    shake.applied_translation = transform.rotation * translation;
+    shake.applied_rotation = rotation;
+
+    transform.translation += shake.applied_translation;
+    transform.rotation = (transform.rotation * rotation).normalize();
+}
+
+pub(super) fn shake_on_knockback(
+    mut commands: Commands,
+    player: Single<&TnuaController<WaltzTnuaCtrlScheme>, With<WaltzPlayer>>,
+    mut last_action: Local<Option<WaltzTnuaCtrlSchemeActionDiscriminant>>,
+) {
+    let action = player.action_discriminant();
+    if action == Some(WaltzTnuaCtrlSchemeActionDiscriminant::Knockback) && *last_action != action {
+        commands.trigger(ShakeCamera::new(CameraShakeSource::Knockback, 1.0));
+    }
+    *last_action = action;
+}
+
+/// Shakes the camera when the player lands faster than `hard_landing_speed`.
+pub(super) fn shake_on_landing(
+    landed: On<CharacterLanded>,
+    mut commands: Commands,
+    players: Query<(), With<WaltzPlayer>>,
+    config: Res<CameraConfig>,
+) {
+    if !players.contains(landed.entity) {
+        return;
+    }
+
+    let hard_landing_speed = config.shake.hard_landing_speed;
+    if landed.speed > hard_landing_speed {
+        let trauma = (landed.speed - hard_landing_speed) / hard_landing_speed.max(1e-3);
+        commands.trigger(ShakeCamera::new(
+            CameraShakeSource::Landing,
+            trauma.min(1.0),
+        ));
+    }
+}
@@ -4,40 +4,92 @@
 use avian3d::prelude::*;
 use bevy::prelude::*;
 
-use crate::camera::{
-    IngameCameraKind, WaltzCamera, WaltzCameraAnchor,
-    config::{CameraConfig, CollisionLayer},
+use crate::{
+    camera::{
+        CameraFocusTarget, IngameCameraKind, WaltzCamera, WaltzCameraAnchor, clamp_view_pitch,
+        config::{CameraConfig, CameraSmoothing, CollisionLayer, Framing},
+    },
+    utils::Vec3Ext,
 };
 
-fn calc_distance_from_hit(hit: RayHitData, direction: Dir3, min_distance: f32) -> f32 {
-    let adjacent_side = min_distance;
-    let angle = direction.angle_between(-hit.normal);
-    let hypotenuse = adjacent_side / angle.cos();
-    hit.distance - hypotenuse
+/// Radius of a sphere around the camera that contains the whole near plane,
+/// so an unobstructed sphere guarantees the near plane never cuts into an obstacle.
+fn near_plane_radius(projection: &Projection) -> f32 {
+    match projection {
+        Projection::Perspective(perspective) => {
+            let half_height = perspective.near * (perspective.fov * 0.5).tan();
+            let half_width = half_height * perspective.aspect_ratio;
+            Vec3::new(half_width, half_height, perspective.near).length()
+        }
+        Projection::Orthographic(orthographic) => orthographic.near.abs().max(0.1),
+        _ => 0.1,
+    }
+}
+
+/// Resolves the camera distance from a shape cast hit along `direction`.
+///
+/// `hit_distance` is the distance the cast sphere travelled before touching the obstacle and
+/// `hit_normal` the surface normal at the contact. The camera is pulled further in so that it
+/// keeps `min_distance_to_objects` away from the surface, measured along the surface normal.
+/// Grazing hits are limited so that a wall parallel to the view does not collapse the distance.
+pub(crate) fn resolve_hit_distance(
+    hit_distance: f32,
+    hit_normal: Vec3,
+    direction: Dir3,
+    min_distance_to_objects: f32,
+    max_distance: f32,
+) -> f32 {
+    const MIN_FACING: f32 = 0.25;
+
+    let facing = direction
+        .dot(-hit_normal.normalize_or_zero())
+        .max(MIN_FACING);
+    let correction = min_distance_to_objects / facing;
+
+    (hit_distance - correction).clamp(0.0, max_distance)
 }
 
 fn get_distance_to_collision(
     spatial_query: &SpatialQuery,
     config: &CameraConfig,
     camera: &WaltzCamera,
-    anchor: &Transform,
+    pivot: Vec3,
     direction: Dir3,
+    max_distance: f32,
+    radius: f32,
 ) -> f32 {
-    let _min_distance = match camera.kind {
+    let min_distance = match camera.kind {
         IngameCameraKind::ThirdPerson => config.third_person.min_distance_to_objects,
-        _ => unreachable!(),
+        // the fixed angle camera stays far above the anchor, only the hit itself matters
+        IngameCameraKind::FixedAngle | IngameCameraKind::FirstPerson => 0.0,
     };
 
-    let solid = true;
     let filter = SpatialQueryFilter::from_mask(CollisionLayer::CameraObstacle.to_bits());
-
-    let max_distance = camera.desired_distance;
-    let origin = anchor.translation;
+    let shape_config = ShapeCastConfig {
+        max_distance,
+        // an obstacle overlapping the pivot is handled by the anchor, not by the camera
+        ignore_origin_penetration: true,
+        ..Default::default()
+    };
 
     spatial_query
-        .cast_ray(origin, direction, max_distance, solid, &filter)
-        // .map(|hit| calc_distance_from_hit(hit, direction, min_distance))
-        .map(|hit| hit.distance)
+        .cast_shape(
+            &Collider::sphere(radius),
+            pivot,
+            Quat::IDENTITY,
+            direction,
+            &shape_config,
+            &filter,
+        )
+        .map(|hit| {
+            resolve_hit_distance(
+                hit.distance,
+                hit.normal1,
+                direction,
+                min_distance,
+                max_distance,
+            )
+        })
         .unwrap_or(max_distance)
 }
 
@@ -45,46 +97,510 @@ fn calc_target_distance(
     spatial_query: &SpatialQuery,
     config: &CameraConfig,
     camera: &WaltzCamera,
-    anchor: &Transform,
+    pivot: Vec3,
     direction: Dir3,
+    distance: f32,
+    radius: f32,
 ) -> f32 {
     match camera.kind {
-        IngameCameraKind::ThirdPerson => {
-            get_distance_to_collision(spatial_query, config, camera, anchor, direction)
+        IngameCameraKind::ThirdPerson | IngameCameraKind::FixedAngle => get_distance_to_collision(
+            spatial_query,
+            config,
+            camera,
+            pivot,
+            direction,
+            distance,
+            radius,
+        ),
+        IngameCameraKind::FirstPerson => distance,
+    }
+}
+
+/// Pulls the camera in immediately when an obstacle gets in between,
+/// but pushes it out slowly once the obstacle is gone, so it does not pop back and forth.
+fn update_collision_distance(
+    config: &CameraConfig,
+    camera: &mut WaltzCamera,
+    distance: f32,
+    target_distance: f32,
+    dt: f32,
+) {
+    let obstructed = target_distance < distance - 1e-3;
+
+    if !obstructed && camera.collision_distance >= distance - 1e-3 {
+        // nothing in between, the zoom already smooths the distance
+        camera.collision_distance = target_distance;
+    } else if target_distance < camera.collision_distance {
+        camera.collision_distance = target_distance;
+    } else {
+        let smoothing = config
+            .zoom(&camera.kind)
+            .map(|zoom| zoom.out_smoothing)
+            .unwrap_or_default();
+        camera.collision_distance.smooth_nudge(
+            &target_distance,
+            config.decay_rate_for(smoothing),
+            dt,
+        );
+    }
+}
+
+/// Vertical field of view and aspect ratio of the camera.
+fn view_frustum(projection: &Projection) -> (f32, f32) {
+    match projection {
+        Projection::Perspective(perspective) => (perspective.fov, perspective.aspect_ratio),
+        _ => {
+            let perspective = PerspectiveProjection::default();
+            (perspective.fov, perspective.aspect_ratio)
         }
-        IngameCameraKind::FixedAngle | IngameCameraKind::FirstPerson => camera.desired_distance,
+    }
+}
+
+/// The camera translation and look at point that keep both `player` and `target` on screen,
+/// with the middle of both placed at the screen offset of the `framing`.
+fn calc_framing(
+    framing: &Framing,
+    projection: &Projection,
+    player: Vec3,
+    target: Vec3,
+) -> (Vec3, Vec3) {
+    let (fov, aspect_ratio) = view_frustum(projection);
+    let tan_half_height = (fov * 0.5).tan().max(1e-3);
+    let tan_half_width = tan_half_height * aspect_ratio;
+
+    // look from behind the player, turned to the side to see over the shoulder
+    let behind = (player - target).horizontal().normalize_or(Vec3::Z);
+    let yaw = Quat::from_rotation_y(framing.angle.to_radians());
+    let direction = clamp_view_pitch(yaw * behind, framing.pitch, framing.pitch);
+
+    let separation = player - target;
+    let half_width = separation.horizontal().length() * 0.5 + framing.padding;
+    let half_height = separation.y.abs() * 0.5 + framing.padding;
+    let distance = (half_width / tan_half_width)
+        .max(half_height / tan_half_height)
+        .clamp(framing.min_distance, framing.max_distance);
+
+    let forward = -direction;
+    let right = forward.cross(Vec3::Y).normalize_or_zero();
+    let up = right.cross(forward);
+
+    let middle = (player + target) * 0.5;
+    let look_at = middle
+        - right * (framing.screen_offset_x * distance * tan_half_width)
+        - up * (framing.screen_offset_y * distance * tan_half_height);
+
+    (look_at + direction * distance, look_at)
+}
+
+/// Moves the framing blend weight towards the secondary or lock-on target being set or cleared.
+fn update_framing(
+    config: &CameraConfig,
+    camera: &mut WaltzCamera,
+    targets: &Query<&GlobalTransform>,
+    dt: f32,
+) {
+    let focus_point = |target: CameraFocusTarget| match target {
+        CameraFocusTarget::Entity(entity) => targets
+            .get(entity)
+            .ok()
+            .map(|transform| transform.translation()),
+        CameraFocusTarget::EntityOffset(entity, offset) => targets
+            .get(entity)
+            .ok()
+            .map(|transform| transform.translation() + offset),
+        CameraFocusTarget::Point(point) => Some(point),
+    };
+
+    let secondary_point = camera.secondary_target.and_then(focus_point);
+    if secondary_point.is_none() && camera.secondary_target.take().is_some() {
+        debug!("camera focus target is gone, release the framing");
+    }
+    let lock_on_point = camera.lock_on_target.and_then(focus_point);
+    if lock_on_point.is_none() && camera.lock_on_target.take().is_some() {
+        debug!("camera lock-on target is gone, release the framing");
+    }
+
+    // a dialogue focus takes precedence, the lock-on is framed again once it is released
+    let point = secondary_point.or(lock_on_point);
+    if let Some(point) = point {
+        camera.framing_point = point;
+    }
+
+    let step = dt / config.framing.blend_duration.max(1e-3);
+    camera.framing = if point.is_some() {
+        (camera.framing + step).min(1.0)
+    } else {
+        (camera.framing - step).max(0.0)
+    };
+}
+
+/// Blends the camera translation and look at point into the framing by its weight.
+fn blend_framing(
+    config: &CameraConfig,
+    camera: &WaltzCamera,
+    projection: &Projection,
+    player: Vec3,
+    (translation, look_at): (Vec3, Vec3),
+) -> (Vec3, Vec3) {
+    if camera.framing <= 0.0 {
+        return (translation, look_at);
+    }
+
+    let (framing_translation, framing_look_at) =
+        calc_framing(&config.framing, projection, player, camera.framing_point);
+    let weight = camera.framing * camera.framing * (3.0 - 2.0 * camera.framing);
+    (
+        translation.lerp(framing_translation, weight),
+        look_at.lerp(framing_look_at, weight),
+    )
+}
+
+/// Moves the aim blend weight and the shoulder towards the requested ones.
+fn update_aim(config: &CameraConfig, camera: &mut WaltzCamera, dt: f32) {
+    let step = dt / config.aim.blend_duration.max(1e-3);
+    camera.aim = if camera.is_aiming() {
+        (camera.aim + step).min(1.0)
+    } else {
+        (camera.aim - step).max(0.0)
+    };
+
+    // the shoulders are 2.0 apart
+    let step = 2.0 * dt / config.aim.shoulder_swap_duration.max(1e-3);
+    camera.shoulder += (camera.shoulder_side - camera.shoulder).clamp(-step, step);
+}
+
+/// Offset of the aim pivot from the anchor pivot, shortened when a wall is next to the shoulder.
+fn calc_shoulder_offset(
+    spatial_query: &SpatialQuery,
+    config: &CameraConfig,
+    camera: &WaltzCamera,
+    pivot: Vec3,
+    direction: Dir3,
+    weight: f32,
+    radius: f32,
+) -> Vec3 {
+    let right = (-direction).cross(Vec3::Y).normalize_or_zero();
+    let offset = weight
+        * (right * camera.shoulder * config.aim.shoulder_offset_x
+            + Vec3::Y * config.aim.shoulder_offset_y);
+
+    let Ok((offset_direction, length)) = Dir3::new_and_length(offset) else {
+        return offset;
+    };
+
+    let filter = SpatialQueryFilter::from_mask(CollisionLayer::CameraObstacle.to_bits());
+    let shape_config = ShapeCastConfig {
+        max_distance: length,
+        ignore_origin_penetration: true,
+        ..Default::default()
+    };
+
+    spatial_query
+        .cast_shape(
+            &Collider::sphere(radius),
+            pivot,
+            Quat::IDENTITY,
+            offset_direction,
+            &shape_config,
+            &filter,
+        )
+        .map(|hit| offset_direction * hit.distance)
+        .unwrap_or(offset)
+}
+
+/// The smoothing of the camera kind, blended from the transition smoothing while switching kinds.
+fn calc_smoothing(config: &CameraConfig, camera: &WaltzCamera) -> CameraSmoothing {
+    let smoothing = config.smoothing(&camera.kind);
+    let blend = |value: f32| {
+        let start = config.transition_smoothing;
+        (start + (value - start) * camera.transition).max(value)
+    };
+
+    CameraSmoothing {
+        translation: blend(smoothing.translation),
+        rotation: blend(smoothing.rotation),
     }
 }
 
 pub(super) fn follow_anchor(
     mut queries: ParamSet<(
-        Single<&mut Transform, With<WaltzCamera>>,
+        Single<(&mut Transform, &mut Projection), With<WaltzCamera>>,
         Single<&Transform, With<WaltzCameraAnchor>>,
     )>,
     time: Res<Time>,
-    camera: Single<&WaltzCamera>,
+    mut camera: Single<&mut WaltzCamera>,
     spatial_query: SpatialQuery,
     config: Res<CameraConfig>,
+    targets: Query<&GlobalTransform>,
 ) {
     let anchor = queries.p1().clone();
-    let mut waltz_transform = queries.p0();
+    let (mut waltz_transform, mut projection) = queries.p0().into_inner();
+
+    let dt = time.delta_secs();
 
-    let direction = Dir3::new(camera.direction).unwrap_or(Dir3::new(Vec3::NEG_Z).unwrap());
+    if camera.transition < 1.0 {
+        camera.transition =
+            (camera.transition + dt / config.transition_duration.max(1e-3)).min(1.0);
+    }
+
+    let (min_pitch, max_pitch) = config.pitch_range(&camera.kind);
+    let direction = Dir3::new(clamp_view_pitch(camera.direction, min_pitch, max_pitch))
+        .unwrap_or(Dir3::new(Vec3::Z).unwrap());
+
+    update_aim(&config, &mut camera, dt);
+    let aim_weight = camera.aim * camera.aim * (3.0 - 2.0 * camera.aim);
+    let radius = near_plane_radius(&projection);
+
+    let anchor_pivot = anchor.translation + camera.height * Vec3::Y;
+    let shoulder_offset = match camera.kind {
+        IngameCameraKind::ThirdPerson => calc_shoulder_offset(
+            &spatial_query,
+            &config,
+            &camera,
+            anchor_pivot,
+            direction,
+            aim_weight,
+            radius,
+        ),
+        IngameCameraKind::FirstPerson | IngameCameraKind::FixedAngle => Vec3::ZERO,
+    };
+    let pivot = anchor_pivot + shoulder_offset;
+
+    update_framing(&config, &mut camera, &targets, dt);
+    let player = anchor.translation + camera.target;
+
+    let (target_translation, look_at) = match camera.kind {
+        IngameCameraKind::ThirdPerson | IngameCameraKind::FixedAngle => {
+            let distance = camera.distance + (config.aim.distance - camera.distance) * aim_weight;
+            let (translation, look_at) = blend_framing(
+                &config,
+                &camera,
+                &projection,
+                player,
+                (pivot + direction * distance, player + shoulder_offset),
+            );
+
+            // the framed translation is solved against the obstacles like the orbit
+            let (view_direction, distance) =
+                Dir3::new_and_length(translation - pivot).unwrap_or((direction, 0.0));
+            let expect_distance = calc_target_distance(
+                &spatial_query,
+                &config,
+                &camera,
+                pivot,
+                view_direction,
+                distance,
+                radius,
+            );
+            update_collision_distance(&config, &mut camera, distance, expect_distance, dt);
+
+            (pivot + view_direction * camera.collision_distance, look_at)
+        }
+        IngameCameraKind::FirstPerson => {
+            // the eye sits on the look at target and looks away from the orbit direction
+            blend_framing(
+                &config,
+                &camera,
+                &projection,
+                player,
+                (player, player - direction.as_vec3()),
+            )
+        }
+    };
 
-    let expect_distance =
-        calc_target_distance(&spatial_query, &config, &camera, &anchor, direction);
-    let target_translation =
-        anchor.translation + direction * expect_distance + camera.height * Vec3::Y;
     debug!(
         "anchor translation {}, target translation {}",
         anchor.translation, target_translation
     );
 
-    let dt = time.delta_secs();
+    let smoothing = calc_smoothing(&config, &camera);
 
-    waltz_transform
-        .translation
-        .smooth_nudge(&target_translation, config.decay_rate, dt);
+    waltz_transform.translation.smooth_nudge(
+        &target_translation,
+        config.decay_rate_for(smoothing.translation),
+        dt,
+    );
 
-    waltz_transform.look_at(anchor.translation + camera.target, Vec3::Y);
+    // the translation smoothing must never drag the camera behind an obstacle
+    if matches!(
+        camera.kind,
+        IngameCameraKind::ThirdPerson | IngameCameraKind::FixedAngle
+    ) {
+        let offset = waltz_transform.translation - pivot;
+        if offset.length() > camera.collision_distance {
+            waltz_transform.translation =
+                pivot + offset.normalize_or_zero() * camera.collision_distance;
+        }
+    }
+
+    let target_rotation = waltz_transform.looking_at(look_at, Vec3::Y).rotation;
+    waltz_transform.rotation.smooth_nudge(
+        &target_rotation,
+        config.decay_rate_for(smoothing.rotation),
+        dt,
+    );
+
+    // narrow the view while aiming
+    let fov = (config.fov + (config.aim.fov - config.fov) * aim_weight).to_radians();
+    let fov_changed = matches!(
+        &*projection,
+        Projection::Perspective(perspective) if (perspective.fov - fov).abs() > 1e-4
+    );
+    if fov_changed {
+        if let Projection::Perspective(perspective) = projection.as_mut() {
+            perspective.fov = fov;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use bevy::{ecs::system::RunSystemOnce, time::TimeUpdateStrategy};
+
+    use super::*;
+
+    #[test]
+    fn head_on_hits_keep_the_min_distance() {
+        let distance = resolve_hit_distance(5.0, Vec3::Z, Dir3::NEG_Z, 0.5, 10.0);
+        assert!((distance - 4.5).abs() < 1e-5);
+
+        // the surface is 60 degrees off, the camera pulls in twice as far to keep its distance
+        let normal = Vec3::new(3f32.sqrt() * 0.5, 0.0, 0.5);
+        let distance = resolve_hit_distance(5.0, normal, Dir3::NEG_Z, 0.5, 10.0);
+        assert!((distance - 4.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn grazing_hits_are_clamped() {
+        // a wall parallel to the view would divide by zero, it counts as a quarter facing
+        let distance = resolve_hit_distance(5.0, Vec3::X, Dir3::NEG_Z, 0.5, 10.0);
+        assert!((distance - 3.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn corrected_distance_stays_in_range() {
+        assert_eq!(
+            resolve_hit_distance(0.2, Vec3::Z, Dir3::NEG_Z, 0.5, 10.0),
+            0.0
+        );
+        assert_eq!(
+            resolve_hit_distance(20.0, Vec3::Z, Dir3::NEG_Z, 0.5, 10.0),
+            10.0
+        );
+        // a zero normal counts as grazing instead of producing NaN
+        assert_eq!(
+            resolve_hit_distance(5.0, Vec3::ZERO, Dir3::NEG_Z, 0.5, 10.0),
+            3.0
+        );
+    }
+
+    #[test]
+    fn walls_between_anchor_and_camera_pull_it_in() {
+        let mut app = App::new();
+        app.add_plugins((
+            MinimalPlugins,
+            TransformPlugin,
+            AssetPlugin::default(),
+            PhysicsPlugins::default(),
+        ))
+        .init_asset::<Mesh>()
+        .insert_resource(TimeUpdateStrategy::ManualDuration(Duration::from_secs_f64(
+            1.0 / 64.0,
+        )));
+        // the front of the wall is 4.75 behind the pivot
+        app.world_mut().spawn((
+            Transform::from_xyz(0.0, 0.0, -5.0),
+            RigidBody::Static,
+            Collider::cuboid(4.0, 4.0, 0.5),
+            CollisionLayers::new(CollisionLayer::CameraObstacle, LayerMask::ALL),
+        ));
+        // the colliders are queried once the physics ran
+        for _ in 0..4 {
+            app.update();
+        }
+
+        let distance_along = |direction: Dir3| {
+            move |spatial_query: SpatialQuery| {
+                get_distance_to_collision(
+                    &spatial_query,
+                    &CameraConfig::default(),
+                    &WaltzCamera::default(),
+                    Vec3::ZERO,
+                    direction,
+                    10.0,
+                    0.2,
+                )
+            }
+        };
+
+        let blocked = app
+            .world_mut()
+            .run_system_once(distance_along(Dir3::NEG_Z))
+            .unwrap();
+        let min_distance = CameraConfig::default().third_person.min_distance_to_objects;
+        assert!((blocked - (4.75 - 0.2 - min_distance)).abs() < 1e-3);
+
+        let free = app
+            .world_mut()
+            .run_system_once(distance_along(Dir3::Z))
+            .unwrap();
+        assert_eq!(free, 10.0);
+    }
+
+    #[test]
+    fn dialogue_focus_takes_precedence_over_the_lock_on() {
+        let mut world = World::new();
+        let frame = |camera: WaltzCamera| {
+            move |targets: Query<&GlobalTransform>| {
+                let mut camera = camera.clone();
+                update_framing(&CameraConfig::default(), &mut camera, &targets, 0.1);
+                camera
+            }
+        };
+
+        let mut camera = WaltzCamera {
+            secondary_target: Some(CameraFocusTarget::Point(Vec3::X)),
+            lock_on_target: Some(CameraFocusTarget::Point(Vec3::Z)),
+            ..default()
+        };
+        camera = world.run_system_once(frame(camera)).unwrap();
+        assert_eq!(camera.framing_point, Vec3::X);
+        assert!(camera.framing > 0.0);
+
+        // the dialogue ends, the lock-on is framed again without being set again
+        camera.secondary_target = None;
+        camera = world.run_system_once(frame(camera)).unwrap();
+        assert_eq!(camera.framing_point, Vec3::Z);
+        assert_eq!(
+            camera.lock_on_target,
+            Some(CameraFocusTarget::Point(Vec3::Z))
+        );
+    }
+
+    #[test]
+    fn focus_on_a_despawned_entity_is_released() {
+        let mut world = World::new();
+        let gone = world.spawn_empty().id();
+        world.despawn(gone);
+
+        let camera = WaltzCamera {
+            secondary_target: Some(CameraFocusTarget::Point(Vec3::X)),
+            lock_on_target: Some(CameraFocusTarget::Entity(gone)),
+            ..default()
+        };
+        let camera = world
+            .run_system_once(move |targets: Query<&GlobalTransform>| {
+                let mut camera = camera.clone();
+                update_framing(&CameraConfig::default(), &mut camera, &targets, 0.1);
+                camera
+            })
+            .unwrap();
+        assert_eq!(camera.lock_on_target, None);
+        assert_eq!(
+            camera.secondary_target,
+            Some(CameraFocusTarget::Point(Vec3::X))
+        );
+    }
 }
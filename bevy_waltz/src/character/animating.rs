@@ -1,4 +1,4 @@
-use bevy::{animation::RepeatAnimation, log, platform::collections::HashMap, prelude::*};
+use bevy::{animation::AnimationTargetId, log, platform::collections::HashMap, prelude::*};
 use bevy_tnua::{
     TnuaAnimatingState, TnuaAnimatingStateDirective,
     builtins::{TnuaBuiltinClimbMemory, TnuaBuiltinJumpMemory},
@@ -6,47 +6,221 @@ use bevy_tnua::{
     prelude::TnuaController,
 };
 
-use crate::character::{WaltzTnuaCtrlScheme, WaltzTnuaCtrlSchemeActionState};
+use crate::character::{
+    WaltzTnuaCtrlScheme, WaltzTnuaCtrlSchemeActionState,
+    animation_table::{AnimationStateKind, AnimationTable, ResolvedClip},
+    root_motion::RootBone,
+    upper_body::UpperBodyLayer,
+};
 
 #[derive(Component)]
 pub struct GltfSceneHandler {
     pub names_from: Handle<Gltf>,
+    /// maps the animation states to the clips of the glTF
+    pub animation_table: Handle<AnimationTable>,
 }
 
 #[derive(Component)]
 pub struct AnimationsHandler {
     pub player_entity: Entity,
-    pub animations: HashMap<String, AnimationNodeIndex>,
+    pub clips: HashMap<AnimationStateKind, ResolvedClip>,
+    pub table: AnimationTable,
+    /// aims and fires over the animation states, if the table has the layer
+    pub upper_body: Option<UpperBodyLayer>,
+    /// the bone measured for the root motion, if the table names one
+    pub root_bone: Option<RootBone>,
+    /// the state playing, `None` until the first one starts
+    pub state: Option<AnimationStateKind>,
+    crossfade: Crossfade,
 }
 
+impl AnimationsHandler {
+    /// The clip nodes of the state playing, without the ones fading out.
+    pub fn current_nodes(&self) -> &[AnimationNodeIndex] {
+        &self.crossfade.current
+    }
+}
+
+/// Fades the clips of the new state in while the clips of the previous states fade out, the
+/// fade is done on the weight of the active animations.
+#[derive(Debug, Default)]
+struct Crossfade {
+    current: Vec<AnimationNodeIndex>,
+    /// weight gained per second by the current clips
+    fade_in: f32,
+    /// the clips of the previous states with the weight they lose per second
+    fading: Vec<(AnimationNodeIndex, f32)>,
+}
+
+impl Crossfade {
+    /// Starts the clips that are not playing yet, clips shared with the previous state keep
+    /// playing and only fade back in.
+    fn start(
+        &mut self,
+        player: &mut AnimationPlayer,
+        nodes: Vec<AnimationNodeIndex>,
+        duration: f32,
+    ) {
+        let rate = if duration > 0.0 {
+            duration.recip()
+        } else {
+            f32::INFINITY
+        };
+
+        for node in self.current.drain(..) {
+            if !nodes.contains(&node) {
+                self.fading.push((node, rate));
+            }
+        }
+        self.fading.retain(|(node, _)| !nodes.contains(node));
+
+        let initial_weight = if duration > 0.0 { 0.0 } else { 1.0 };
+        for node in &nodes {
+            if !player.is_playing_animation(*node) {
+                player.start(*node).set_weight(initial_weight);
+            }
+        }
+
+        self.current = nodes;
+        self.fade_in = rate;
+    }
+
+    fn update(&mut self, player: &mut AnimationPlayer, dt: f32) {
+        for node in &self.current {
+            if let Some(animation) = player.animation_mut(*node) {
+                let weight = (animation.weight() + self.fade_in * dt).min(1.0);
+                animation.set_weight(weight);
+            }
+        }
+
+        self.fading.retain(|(node, rate)| {
+            let Some(animation) = player.animation_mut(*node) else {
+                return false;
+            };
+
+            let weight = animation.weight() - rate * dt;
+            if weight <= 0.0 {
+                player.stop(*node);
+                return false;
+            }
+            animation.set_weight(weight);
+            true
+        });
+    }
+
+    /// The active animations of the current state.
+    fn current_animations<'a>(
+        &'a self,
+        player: &'a mut AnimationPlayer,
+    ) -> impl Iterator<Item = (AnimationNodeIndex, &'a mut ActiveAnimation)> {
+        player
+            .playing_animations_mut()
+            .filter(|(node, _)| self.current.contains(node))
+            .map(|(node, animation)| (*node, animation))
+    }
+}
+
+/// Builds the animation graph of the character once both its glTF and its animation table are
+/// loaded with their dependencies, until then the animation player is left without a graph and
+/// retried every frame.
 pub fn animation_patcher_system(
-    animation_players_query: Query<Entity, Added<AnimationPlayer>>,
+    animation_players_query: Query<Entity, (With<AnimationPlayer>, Without<AnimationGraphHandle>)>,
     parents_query: Query<&ChildOf>,
+    children_query: Query<&Children>,
+    joints_query: Query<(Option<&Name>, Option<&AnimationTargetId>)>,
+    bones_query: Query<(&Name, &Transform)>,
     scene_handlers_query: Query<&GltfSceneHandler>,
+    asset_server: Res<AssetServer>,
     gltf_assets: Res<Assets<Gltf>>,
+    animation_tables: Res<Assets<AnimationTable>>,
+    clip_assets: Res<Assets<AnimationClip>>,
     mut animation_graphs_assets: ResMut<Assets<AnimationGraph>>,
     mut commands: Commands,
 ) {
     for player_entity in animation_players_query {
-        log::info!("player entity is {player_entity}");
         let mut entity = player_entity;
         loop {
-            if let Ok(GltfSceneHandler { names_from }) = scene_handlers_query.get(entity) {
-                let gltf = gltf_assets.get(names_from).unwrap();
+            if let Ok(GltfSceneHandler {
+                names_from,
+                animation_table,
+            }) = scene_handlers_query.get(entity)
+            {
+                // the table is validated against the glTF first
+                if !asset_server.is_loaded_with_dependencies(names_from)
+                    || !asset_server.is_loaded_with_dependencies(animation_table)
+                {
+                    break;
+                }
+                let (Some(gltf), Some(animation_table)) = (
+                    gltf_assets.get(names_from),
+                    animation_tables.get(animation_table),
+                ) else {
+                    break;
+                };
+
+                log::info!("player entity is {player_entity}");
                 let mut graph = AnimationGraph::new();
-                let root_node = graph.root;
+                // the states play below the locomotion node, the upper body layer masks it
+                let locomotion = graph.add_blend(1.0, graph.root);
                 let mut animations = HashMap::<String, AnimationNodeIndex>::new();
+                let mut named_clips = HashMap::<String, Handle<AnimationClip>>::new();
 
                 for (name, clip) in gltf.named_animations.iter() {
-                    let node_index = graph.add_clip(clip.clone(), 1.0, root_node);
+                    let node_index = graph.add_clip(clip.clone(), 1.0, locomotion);
                     animations.insert(name.to_string(), node_index);
+                    named_clips.insert(name.to_string(), clip.clone());
                 }
 
+                let clips = animation_table.resolve(
+                    &mut graph,
+                    locomotion,
+                    &named_clips,
+                    &animations,
+                    &clip_assets,
+                );
+                let upper_body = animation_table.upper_body.as_ref().and_then(|config| {
+                    let layer = config.resolve(&mut graph, &named_clips, locomotion)?;
+                    config.add_mask_groups(
+                        &mut graph,
+                        player_entity,
+                        &children_query,
+                        &joints_query,
+                    );
+                    Some(layer)
+                });
+                let root_bone = animation_table.root_bone.as_ref().and_then(|name| {
+                    let root_bone =
+                        RootBone::find(name, player_entity, &children_query, &bones_query);
+                    if root_bone.is_none() {
+                        error!("root motion is disabled, root bone {name} does not exist");
+                    }
+                    root_bone
+                });
+
+                let foot_ik = animation_table
+                    .foot_ik
+                    .as_ref()
+                    .and_then(|config| config.rig(player_entity, &children_query, &bones_query));
+                let ragdoll = animation_table.ragdoll.as_ref().and_then(|config| {
+                    config.rig(player_entity, &children_query, &parents_query, &bones_query)
+                });
+
                 let mut cmd = commands.entity(entity);
                 cmd.remove::<GltfSceneHandler>();
+                if let Some(foot_ik) = foot_ik {
+                    cmd.insert(foot_ik);
+                }
+                if let Some(ragdoll) = ragdoll {
+                    cmd.insert(ragdoll);
+                }
                 cmd.insert(AnimationsHandler {
                     player_entity,
-                    animations,
+                    clips,
+                    table: animation_table.clone(),
+                    upper_body,
+                    root_bone,
+                    state: None,
+                    crossfade: Crossfade::default(),
                 });
 
                 commands
@@ -81,80 +255,33 @@ pub enum AnimationState {
     Climbing(Float),
 }
 
-#[derive(Debug)]
-pub struct AnimationProp {
-    name: &'static str,
-    speed: f32,
-    repeat: RepeatAnimation,
-}
+impl AnimationState {
+    pub fn kind(&self) -> AnimationStateKind {
+        match self {
+            AnimationState::Standing => AnimationStateKind::Standing,
+            AnimationState::Running(_) => AnimationStateKind::Running,
+            AnimationState::JumpStart => AnimationStateKind::JumpStart,
+            AnimationState::JumpLoop => AnimationStateKind::JumpLoop,
+            AnimationState::JumpLand => AnimationStateKind::JumpLand,
+            AnimationState::Falling => AnimationStateKind::Falling,
+            AnimationState::Crouching => AnimationStateKind::Crouching,
+            AnimationState::Crawling(_) => AnimationStateKind::Crawling,
+            AnimationState::Dashing => AnimationStateKind::Dashing,
+            AnimationState::KnockedBack => AnimationStateKind::KnockedBack,
+            AnimationState::WallSliding => AnimationStateKind::WallSliding,
+            AnimationState::WallJumping => AnimationStateKind::WallJumping,
+            AnimationState::Climbing(_) => AnimationStateKind::Climbing,
+        }
+    }
 
-pub fn get_animation_prop(ani_state: &AnimationState) -> AnimationProp {
-    match ani_state {
-        AnimationState::Standing => AnimationProp {
-            name: "Idle_Loop",
-            speed: 1.0,
-            repeat: RepeatAnimation::Forever,
-        },
-        AnimationState::Running(speed) => AnimationProp {
-            name: "Walk_Loop",
-            speed: *speed,
-            repeat: RepeatAnimation::Forever,
-        },
-        AnimationState::JumpStart => AnimationProp {
-            name: "Jump_Start",
-            speed: 2.0,
-            repeat: RepeatAnimation::Never,
-        },
-
-        AnimationState::JumpLoop => AnimationProp {
-            name: "Jump_Loop",
-            speed: 1.0,
-            repeat: RepeatAnimation::Forever,
-        },
-        AnimationState::JumpLand => AnimationProp {
-            name: "Jump_Loop",
-            speed: 1.0,
-            repeat: RepeatAnimation::Never,
-        },
-        AnimationState::Falling => AnimationProp {
-            name: "Jump_Loop",
-            speed: 1.0,
-            repeat: RepeatAnimation::Forever,
-        },
-
-        AnimationState::Crouching => AnimationProp {
-            name: "Crouch_Idle_Loop",
-            speed: 1.0,
-            repeat: RepeatAnimation::Forever,
-        },
-
-        AnimationState::Crawling(speed) => AnimationProp {
-            name: "Swim_Fwd_Loop",
-            speed: *speed,
-            repeat: RepeatAnimation::Forever,
-        },
-        AnimationState::Dashing => AnimationProp {
-            name: "Jog_Fwd_Loop",
-            speed: 1.0,
-            repeat: RepeatAnimation::Forever,
-        },
-
-        AnimationState::KnockedBack => AnimationProp {
-            name: "Hit_Chest",
-            speed: 1.0,
-            repeat: RepeatAnimation::Never,
-        },
-        AnimationState::WallSliding => AnimationProp {
-            name: "Swim_Idle_Loop",
-            speed: 1.0,
-            repeat: RepeatAnimation::Forever,
-        },
-        AnimationState::WallJumping => todo!(),
-        AnimationState::Climbing(speed) => AnimationProp {
-            name: "Swim_Idle_Loop",
-            speed: *speed,
-            repeat: RepeatAnimation::Forever,
-        },
+    /// The speed carried by the state, which scales the speed of its clip.
+    pub fn speed(&self) -> Option<Float> {
+        match self {
+            AnimationState::Running(speed)
+            | AnimationState::Crawling(speed)
+            | AnimationState::Climbing(speed) => Some(*speed),
+            _ => None,
+        }
     }
 }
 
@@ -171,14 +298,19 @@ pub fn animate_character(
         // The controller can be used to determine the state of the character - information crucial
         // for deciding which animation to play.
         &TnuaController<WaltzTnuaCtrlScheme>,
-        &AnimationsHandler,
+        &mut AnimationsHandler,
     )>,
-    mut animation_players_query: Query<&mut AnimationPlayer>,
+    mut animation_players_query: Query<(&mut AnimationPlayer, &AnimationGraphHandle)>,
+    mut animation_graphs_assets: ResMut<Assets<AnimationGraph>>,
+    time: Res<Time>,
 ) {
-    for (mut animating_state, controller, handler) in animations_handlers_query.iter_mut() {
-        let Ok(mut player) = animation_players_query.get_mut(handler.player_entity) else {
+    for (mut animating_state, controller, mut handler) in animations_handlers_query.iter_mut() {
+        let Ok((mut player, graph_handle)) = animation_players_query.get_mut(handler.player_entity)
+        else {
             continue;
         };
+        let handler = &mut *handler;
+        handler.crossfade.update(&mut player, time.delta_secs());
 
         // We use the action name because it's faster than trying to cast into each action
         // type. We'd still have to cast into the action type later though, to get
@@ -249,6 +381,7 @@ pub fn animate_character(
         // We need to determine the animating status of the character on each frame, and feed it to
         // `update_by_discriminant` which will decide whether or not we need to switch the
         // animation.
+        let current_kind = current_animation.kind();
         match animating_state.update_by_discriminant(current_animation) {
             // `Maintain` means that the same animation state continues from the previous frame, so
             // we shouldn't switch the animation.
@@ -259,15 +392,26 @@ pub fn animate_character(
                 AnimationState::Running(speed)
                 | AnimationState::Crawling(speed)
                 | AnimationState::Climbing(speed) => {
-                    for (_, active_animation) in player.playing_animations_mut() {
-                        active_animation.set_speed(*speed as f32);
+                    let clip = handler.clips.get(&state.kind());
+                    // the blend space follows the running speed below
+                    if let Some(clip) = clip.filter(|clip| clip.samples.is_empty()) {
+                        let clip_speed = clip.playback_speed(Some(*speed as f32));
+                        for (_, active_animation) in
+                            handler.crossfade.current_animations(&mut player)
+                        {
+                            active_animation.set_speed(clip_speed);
+                        }
                     }
                 }
                 // Jumping and dashing can be chained, we want to start a new jump/dash animation
                 // when one jump/dash is chained to another.
                 AnimationState::JumpStart | AnimationState::Dashing => {
                     if controller.action_flow_status().just_starting().is_some() {
-                        player.seek_all_by(0.0);
+                        for (_, active_animation) in
+                            handler.crossfade.current_animations(&mut player)
+                        {
+                            active_animation.seek_to(0.0);
+                        }
                     }
                 }
                 // For other animations we don't have anything special to do - so we just let them
@@ -278,25 +422,85 @@ pub fn animate_character(
             // start a new animation. The actual implementation for each possiable animation state
             // is straightforward - we start the animation, set its speed if the state has a
             // variable speed, and set it to repeat if it's something that needs to repeat.
-            TnuaAnimatingStateDirective::Alter {
-                old_state: _,
-                state,
-            } => {
-                let animation_prop = get_animation_prop(state);
-                if let Some(animation) = handler.animations.get(animation_prop.name) {
-                    player.stop_all();
-                    trace!(
-                        "animation {} speed: {}",
-                        animation_prop.name, animation_prop.speed
-                    );
-                    player
-                        .start(*animation)
-                        .set_speed(animation_prop.speed)
-                        .set_repeat(animation_prop.repeat);
-                } else {
-                    todo!("{} not exist", animation_prop.name)
+            TnuaAnimatingStateDirective::Alter { old_state, state } => {
+                handler.state = Some(state.kind());
+                // the missing clips were reported when the graph was built
+                let Some(clip) = handler.clips.get(&state.kind()) else {
+                    continue;
+                };
+
+                let duration = handler
+                    .table
+                    .transition(old_state.as_ref().map(AnimationState::kind), state.kind());
+                let speed = clip.playback_speed(state.speed().map(|speed| speed as f32));
+                trace!("animation {state:?} speed: {speed}, crossfade {duration}s");
+
+                // a sample still playing keeps its phase, the others join it
+                let phase = clip
+                    .samples
+                    .iter()
+                    .find_map(|sample| {
+                        let animation = player.animation(sample.node)?;
+                        Some(animation.seek_time() / sample.duration.max(1e-3))
+                    })
+                    .unwrap_or_default()
+                    .fract();
+
+                handler
+                    .crossfade
+                    .start(&mut player, clip.clip_nodes(), duration);
+                for (_, active_animation) in handler.crossfade.current_animations(&mut player) {
+                    active_animation.set_speed(speed).set_repeat(clip.repeat);
+                }
+                for sample in &clip.samples {
+                    if let Some(animation) = player.animation_mut(sample.node) {
+                        animation.seek_to(phase * sample.duration);
+                    }
+                }
+            }
+        }
+
+        // weight the samples of a blend space by the running speed, the samples play in phase at
+        // the pace matching the running speed
+        let Some(clip) = handler
+            .clips
+            .get(&current_kind)
+            .filter(|clip| !clip.samples.is_empty())
+        else {
+            continue;
+        };
+
+        let running_speed = controller.basis_memory.running_velocity.length() as f32;
+        let weights = clip.sample_weights(running_speed);
+        // only touch the graph when a weight changed, every mutable access marks it modified
+        let changed = animation_graphs_assets
+            .get(graph_handle)
+            .is_some_and(|graph| {
+                clip.samples.iter().zip(&weights).any(|(sample, weight)| {
+                    graph
+                        .get(sample.node)
+                        .is_some_and(|node| (node.weight - weight).abs() > 1e-3)
+                })
+            });
+        if changed {
+            if let Some(mut graph) = animation_graphs_assets.get_mut(graph_handle) {
+                for (sample, weight) in clip.samples.iter().zip(&weights) {
+                    if let Some(node) = graph.get_mut(sample.node) {
+                        node.weight = *weight;
+                    }
                 }
             }
         }
+
+        // root motion samples play at the pace of their clips, their motion sets the running speed
+        for (sample, speed) in clip
+            .samples
+            .iter()
+            .zip(clip.sample_speeds(running_speed, &weights))
+        {
+            if let Some(animation) = player.animation_mut(sample.node) {
+                animation.set_speed(speed);
+            }
+        }
     }
 }
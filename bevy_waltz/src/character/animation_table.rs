@@ -0,0 +1,717 @@
+//! The clip of every animation state of a character, loaded from a `*.anim.ron` file.
+//!
+//! The table is checked against the `named_animations` of the character glTF as soon as both are
+//! loaded with their dependencies. Every state without a clip in the glTF, or without an entry in
+//! the table, is reported and plays the `fallback` clip once the animation graph is built.
+//!
+//! A state may play a 1D blend space instead of a single clip, its samples are weighted by the
+//! running speed of the character. Switching states crossfades for the duration of the
+//! `transitions` entry of the state pair, or `default_transition`.
+//!
+//! The states marked with `root_motion` move the character by the motion of the `root_bone`, see
+//! [`root_motion`](super::root_motion).
+//!
+//! The optional `upper_body` layer aims and fires over the states, see
+//! [`upper_body`](super::upper_body), the optional `foot_ik` keeps the feet on the ground, see
+//! [`foot_ik`](super::foot_ik), and the optional `ragdoll` takes over the skeleton, see
+//! [`ragdoll`](super::ragdoll).
+use std::fmt;
+
+use bevy::{
+    animation::RepeatAnimation,
+    asset::{AssetLoader, LoadContext, io::Reader},
+    platform::collections::{HashMap, HashSet},
+    prelude::*,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::character::{
+    animating::{GltfSceneHandler, animation_patcher_system},
+    foot_ik::FootIkConfig,
+    ragdoll::RagdollConfig,
+    upper_body::UpperBodyConfig,
+};
+
+pub(super) fn plugin(app: &mut App) {
+    app.init_asset::<AnimationTable>()
+        .register_asset_loader(AnimationTableLoader)
+        .add_systems(
+            Update,
+            validate_animation_tables.before(animation_patcher_system),
+        );
+}
+
+/// [`AnimationState`](super::animating::AnimationState) without its payload.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Reflect, Serialize, Deserialize)]
+pub enum AnimationStateKind {
+    Standing,
+    Running,
+    JumpStart,
+    JumpLoop,
+    JumpLand,
+    Falling,
+    Crouching,
+    Crawling,
+    Dashing,
+    KnockedBack,
+    WallSliding,
+    WallJumping,
+    Climbing,
+}
+
+impl AnimationStateKind {
+    pub const ALL: [AnimationStateKind; 13] = [
+        AnimationStateKind::Standing,
+        AnimationStateKind::Running,
+        AnimationStateKind::JumpStart,
+        AnimationStateKind::JumpLoop,
+        AnimationStateKind::JumpLand,
+        AnimationStateKind::Falling,
+        AnimationStateKind::Crouching,
+        AnimationStateKind::Crawling,
+        AnimationStateKind::Dashing,
+        AnimationStateKind::KnockedBack,
+        AnimationStateKind::WallSliding,
+        AnimationStateKind::WallJumping,
+        AnimationStateKind::Climbing,
+    ];
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum ClipRepeat {
+    Never,
+    #[default]
+    Forever,
+    Count(u32),
+}
+
+impl From<ClipRepeat> for RepeatAnimation {
+    fn from(repeat: ClipRepeat) -> Self {
+        match repeat {
+            ClipRepeat::Never => RepeatAnimation::Never,
+            ClipRepeat::Forever => RepeatAnimation::Forever,
+            ClipRepeat::Count(count) => RepeatAnimation::Count(count),
+        }
+    }
+}
+
+fn default_speed() -> f32 {
+    1.0
+}
+
+fn default_transition() -> f32 {
+    0.2
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ClipEntry {
+    /// the name of the clip in the glTF
+    pub clip: String,
+    /// playback speed, multiplies the speed carried by states like `Running`
+    #[serde(default = "default_speed")]
+    pub speed: f32,
+    #[serde(default)]
+    pub repeat: ClipRepeat,
+    /// the clip moves the character instead of the physics, it plays at its own pace
+    #[serde(default)]
+    pub root_motion: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BlendSample {
+    pub clip: String,
+    /// running speed at which the sample plays alone, at the pace of the clip
+    pub speed: f32,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Transition {
+    pub from: AnimationStateKind,
+    pub to: AnimationStateKind,
+    /// seconds of the crossfade
+    pub duration: f32,
+}
+
+#[derive(Asset, TypePath, Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AnimationTable {
+    /// the clip played, looping, by the states that have no clip
+    pub fallback: String,
+    pub states: std::collections::HashMap<AnimationStateKind, ClipEntry>,
+    /// states playing a blend space instead of a clip, they need no entry in `states`, the
+    /// `clip` of an entry is ignored but its `speed`, `repeat` and `root_motion` still apply
+    #[serde(default)]
+    pub blend_spaces: std::collections::HashMap<AnimationStateKind, Vec<BlendSample>>,
+    #[serde(default = "default_transition")]
+    pub default_transition: f32,
+    #[serde(default)]
+    pub transitions: Vec<Transition>,
+    #[serde(default)]
+    pub upper_body: Option<UpperBodyConfig>,
+    /// the bone carrying the root motion, required by the states with `root_motion`
+    #[serde(default)]
+    pub root_bone: Option<String>,
+    #[serde(default)]
+    pub foot_ik: Option<FootIkConfig>,
+    #[serde(default)]
+    pub ragdoll: Option<RagdollConfig>,
+}
+
+/// A sample of a blend space in the animation graph.
+#[derive(Debug, Clone, Copy)]
+pub struct BlendSampleNode {
+    pub node: AnimationNodeIndex,
+    pub speed: f32,
+    /// seconds of a cycle of the clip at its own pace
+    pub duration: f32,
+}
+
+/// The graph node of a state clip with its playback settings.
+#[derive(Debug, Clone)]
+pub struct ResolvedClip {
+    /// the clip node, or the blend node of a blend space
+    pub node: AnimationNodeIndex,
+    pub speed: f32,
+    pub repeat: RepeatAnimation,
+    pub root_motion: bool,
+    /// the samples of the blend space sorted by speed, empty for a single clip
+    pub samples: Vec<BlendSampleNode>,
+}
+
+impl ResolvedClip {
+    fn single(node: AnimationNodeIndex, speed: f32, repeat: RepeatAnimation) -> Self {
+        Self {
+            node,
+            speed,
+            repeat,
+            root_motion: false,
+            samples: Vec::new(),
+        }
+    }
+
+    /// The playback speed for the speed carried by the state. A root motion clip keeps its own
+    /// pace, the speed of the character follows it, only the direction of the state is kept.
+    pub fn playback_speed(&self, state_speed: Option<f32>) -> f32 {
+        match state_speed {
+            Some(speed) if self.root_motion => self.speed.copysign(speed),
+            Some(speed) => self.speed * speed,
+            None => self.speed,
+        }
+    }
+
+    /// The clip nodes the player plays for the state.
+    pub fn clip_nodes(&self) -> Vec<AnimationNodeIndex> {
+        if self.samples.is_empty() {
+            vec![self.node]
+        } else {
+            self.samples.iter().map(|sample| sample.node).collect()
+        }
+    }
+
+    /// The graph weight of every sample at the running speed, blending the two samples around it.
+    pub fn sample_weights(&self, running_speed: f32) -> Vec<f32> {
+        let mut weights = vec![0.0; self.samples.len()];
+        let Some(upper) = self
+            .samples
+            .iter()
+            .position(|sample| running_speed < sample.speed)
+        else {
+            if let Some(last) = weights.last_mut() {
+                *last = 1.0;
+            }
+            return weights;
+        };
+
+        if upper == 0 {
+            weights[0] = 1.0;
+        } else {
+            let (low, high) = (self.samples[upper - 1].speed, self.samples[upper].speed);
+            let t = (running_speed - low) / (high - low).max(1e-3);
+            weights[upper - 1] = 1.0 - t;
+            weights[upper] = t;
+        }
+        weights
+    }
+
+    /// The playback speed of every sample for the `weights` at the running speed. The samples
+    /// share one cycle length, the cycle lengths they would have alone blended by the weights, so
+    /// they keep the same normalized time and their foot cycles stay in phase.
+    pub fn sample_speeds(&self, running_speed: f32, weights: &[f32]) -> Vec<f32> {
+        // alone, a sample plays at `pace / stride`, root motion samples at the pace of the clip
+        let pace = if self.root_motion {
+            self.speed
+        } else {
+            self.speed * running_speed
+        };
+        let stride = |sample: &BlendSampleNode| {
+            if self.root_motion {
+                1.0
+            } else {
+                sample.speed.max(1e-3)
+            }
+        };
+
+        // the shared cycle lasts `strides / pace` seconds
+        let strides = self
+            .samples
+            .iter()
+            .zip(weights)
+            .map(|(sample, weight)| weight * sample.duration * stride(sample))
+            .sum::<f32>();
+
+        self.samples
+            .iter()
+            .map(|sample| {
+                if strides > 1e-6 {
+                    pace * sample.duration / strides
+                } else {
+                    pace / stride(sample)
+                }
+            })
+            .collect()
+    }
+}
+
+impl AnimationTable {
+    /// Seconds of the crossfade between the states.
+    pub fn transition(&self, from: Option<AnimationStateKind>, to: AnimationStateKind) -> f32 {
+        from.and_then(|from| {
+            self.transitions
+                .iter()
+                .find(|transition| transition.from == from && transition.to == to)
+        })
+        .map_or(self.default_transition, |transition| transition.duration)
+    }
+
+    /// Lists the clips of the table the glTF misses, `has_clip` tells whether the glTF has a
+    /// named clip, with the states falling back because of them, empty when the table is complete.
+    pub fn missing_clips(&self, has_clip: impl Fn(&str) -> bool) -> Vec<String> {
+        let mut missing = Vec::new();
+
+        if !has_clip(&self.fallback) {
+            missing.push(format!("fallback clip {} does not exist", self.fallback));
+        }
+
+        for state in AnimationStateKind::ALL {
+            if let Some(samples) = self.blend_spaces.get(&state) {
+                let missing_samples = samples
+                    .iter()
+                    .filter(|sample| !has_clip(&sample.clip))
+                    .map(|sample| sample.clip.as_str())
+                    .collect::<Vec<_>>();
+                if samples.is_empty() || !missing_samples.is_empty() {
+                    missing.push(format!(
+                        "{state:?} blends missing clips [{}]",
+                        missing_samples.join(", ")
+                    ));
+                }
+                continue;
+            }
+
+            match self.states.get(&state) {
+                None => missing.push(format!("{state:?} has no entry")),
+                Some(entry) if !has_clip(&entry.clip) => {
+                    missing.push(format!("{state:?} plays missing clip {}", entry.clip));
+                }
+                Some(_) => {}
+            }
+        }
+
+        if let Some(upper_body) = &self.upper_body {
+            for clip in [&upper_body.aim, &upper_body.fire] {
+                if !has_clip(clip) {
+                    missing.push(format!("upper body plays missing clip {clip}"));
+                }
+            }
+        }
+
+        if self.root_bone.is_none() && self.states.values().any(|entry| entry.root_motion) {
+            missing.push("states use root motion, but the table has no root bone".to_string());
+        }
+
+        missing
+    }
+
+    /// Resolves the clip of every state to its node in the animation graph, states left without
+    /// a clip fall back to the fallback clip, if the glTF has it. The missing clips are reported
+    /// by [`AnimationTable::missing_clips`] when the table loads.
+    ///
+    /// `animations` are the nodes of the named clips, the blend spaces are added to the graph
+    /// below `parent`, their samples measured from the `clip_assets`.
+    pub fn resolve(
+        &self,
+        graph: &mut AnimationGraph,
+        parent: AnimationNodeIndex,
+        named_clips: &HashMap<String, Handle<AnimationClip>>,
+        animations: &HashMap<String, AnimationNodeIndex>,
+        clip_assets: &Assets<AnimationClip>,
+    ) -> HashMap<AnimationStateKind, ResolvedClip> {
+        let fallback = animations
+            .get(&self.fallback)
+            .map(|node| ResolvedClip::single(*node, 1.0, RepeatAnimation::Forever));
+
+        AnimationStateKind::ALL
+            .into_iter()
+            .filter_map(|state| {
+                if let Some(samples) = self.blend_spaces.get(&state) {
+                    if samples.is_empty()
+                        || !samples
+                            .iter()
+                            .all(|sample| named_clips.contains_key(&sample.clip))
+                    {
+                        return fallback.clone().map(|clip| (state, clip));
+                    }
+
+                    let blend = graph.add_blend(1.0, parent);
+                    let mut samples = samples
+                        .iter()
+                        .map(|sample| {
+                            let clip = &named_clips[&sample.clip];
+                            BlendSampleNode {
+                                node: graph.add_clip(clip.clone(), 0.0, blend),
+                                speed: sample.speed,
+                                duration: clip_assets
+                                    .get(clip)
+                                    .map_or(0.0, AnimationClip::duration),
+                            }
+                        })
+                        .collect::<Vec<_>>();
+                    samples.sort_by(|a, b| a.speed.total_cmp(&b.speed));
+
+                    // the entry of a blend space state is optional, it only sets the playback
+                    let clip = match self.states.get(&state) {
+                        Some(entry) => ResolvedClip {
+                            samples,
+                            root_motion: entry.root_motion,
+                            ..ResolvedClip::single(blend, entry.speed, entry.repeat.into())
+                        },
+                        None => ResolvedClip {
+                            samples,
+                            ..ResolvedClip::single(blend, 1.0, RepeatAnimation::Forever)
+                        },
+                    };
+                    return Some((state, clip));
+                }
+
+                let Some(clip) = self
+                    .states
+                    .get(&state)
+                    .and_then(|entry| Some((entry, animations.get(&entry.clip)?)))
+                    .map(|(entry, node)| ResolvedClip {
+                        root_motion: entry.root_motion,
+                        ..ResolvedClip::single(*node, entry.speed, entry.repeat.into())
+                    })
+                else {
+                    return fallback.clone().map(|clip| (state, clip));
+                };
+
+                Some((state, clip))
+            })
+            .collect()
+    }
+}
+
+/// Reports the clips a table misses in the glTF of its character as soon as both are loaded with
+/// their dependencies, instead of when the model is spawned and its animation graph is built.
+fn validate_animation_tables(
+    handlers: Query<&GltfSceneHandler>,
+    asset_server: Res<AssetServer>,
+    gltf_assets: Res<Assets<Gltf>>,
+    animation_tables: Res<Assets<AnimationTable>>,
+    mut validated: Local<HashSet<(AssetId<Gltf>, AssetId<AnimationTable>)>>,
+) {
+    for GltfSceneHandler {
+        names_from,
+        animation_table,
+    } in &handlers
+    {
+        if !asset_server.is_loaded_with_dependencies(names_from)
+            || !asset_server.is_loaded_with_dependencies(animation_table)
+            || !validated.insert((names_from.id(), animation_table.id()))
+        {
+            continue;
+        }
+        let (Some(gltf), Some(table)) = (
+            gltf_assets.get(names_from),
+            animation_tables.get(animation_table),
+        ) else {
+            continue;
+        };
+
+        let missing = table.missing_clips(|clip| gltf.named_animations.contains_key(clip));
+        if !missing.is_empty() {
+            let path = animation_table
+                .path()
+                .map_or_else(|| "<unnamed>".to_string(), ToString::to_string);
+            error!(
+                "animation table {path} falls back to clip {}: {}",
+                table.fallback,
+                missing.join(", ")
+            );
+        }
+    }
+}
+
+#[derive(Debug)]
+pub(crate) enum AnimationTableError {
+    Io(std::io::Error),
+    Ron(ron::error::SpannedError),
+}
+
+impl fmt::Display for AnimationTableError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AnimationTableError::Io(err) => write!(f, "failed to read animation table: {err}"),
+            AnimationTableError::Ron(err) => write!(f, "invalid animation table: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for AnimationTableError {}
+
+impl From<std::io::Error> for AnimationTableError {
+    fn from(err: std::io::Error) -> Self {
+        AnimationTableError::Io(err)
+    }
+}
+
+impl From<ron::error::SpannedError> for AnimationTableError {
+    fn from(err: ron::error::SpannedError) -> Self {
+        AnimationTableError::Ron(err)
+    }
+}
+
+#[derive(Default, TypePath)]
+struct AnimationTableLoader;
+
+impl AssetLoader for AnimationTableLoader {
+    type Asset = AnimationTable;
+    type Settings = ();
+    type Error = AnimationTableError;
+
+    async fn load(
+        &self,
+        reader: &mut dyn Reader,
+        _settings: &(),
+        _load_context: &mut LoadContext<'_>,
+    ) -> Result<Self::Asset, Self::Error> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).await?;
+
+        Ok(ron::de::from_bytes(&bytes)?)
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["anim.ron"]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A walk of 1s at 3m/s, a jog of 0.8s at 8m/s and a sprint of 0.6s at 14m/s.
+    fn running_blend_space() -> ResolvedClip {
+        let samples = [(3.0, 1.0), (8.0, 0.8), (14.0, 0.6)]
+            .into_iter()
+            .enumerate()
+            .map(|(index, (speed, duration))| BlendSampleNode {
+                node: AnimationNodeIndex::new(index + 1),
+                speed,
+                duration,
+            })
+            .collect();
+        ResolvedClip {
+            samples,
+            ..ResolvedClip::single(AnimationNodeIndex::new(0), 1.0, RepeatAnimation::Forever)
+        }
+    }
+
+    /// A table whose states all play `Idle`, which the glTF of [`has_clip`] has.
+    fn complete_table() -> AnimationTable {
+        let mut table: AnimationTable = ron::from_str(r#"(fallback: "Idle", states: {})"#).unwrap();
+        for state in AnimationStateKind::ALL {
+            table.states.insert(
+                state,
+                ClipEntry {
+                    clip: "Idle".to_string(),
+                    speed: 1.0,
+                    repeat: ClipRepeat::Forever,
+                    root_motion: false,
+                },
+            );
+        }
+        table
+    }
+
+    fn has_clip(clip: &str) -> bool {
+        ["Idle", "Walk", "Jog"].contains(&clip)
+    }
+
+    #[test]
+    fn weights_blend_the_samples_around_the_speed() {
+        let clip = running_blend_space();
+        assert_eq!(clip.sample_weights(5.5), vec![0.5, 0.5, 0.0]);
+        // a sample speed plays that sample alone
+        assert_eq!(clip.sample_weights(3.0), vec![1.0, 0.0, 0.0]);
+        assert_eq!(clip.sample_weights(14.0), vec![0.0, 0.0, 1.0]);
+    }
+
+    #[test]
+    fn weights_are_clamped_to_the_first_and_last_sample() {
+        let clip = running_blend_space();
+        assert_eq!(clip.sample_weights(0.0), vec![1.0, 0.0, 0.0]);
+        assert_eq!(clip.sample_weights(-1.0), vec![1.0, 0.0, 0.0]);
+        assert_eq!(clip.sample_weights(20.0), vec![0.0, 0.0, 1.0]);
+
+        let single = ResolvedClip::single(AnimationNodeIndex::new(0), 1.0, RepeatAnimation::Never);
+        assert!(single.sample_weights(5.0).is_empty());
+    }
+
+    #[test]
+    fn transitions_fall_back_to_the_default() {
+        let mut table = complete_table();
+        table.transitions.push(Transition {
+            from: AnimationStateKind::Standing,
+            to: AnimationStateKind::Running,
+            duration: 0.5,
+        });
+
+        use AnimationStateKind::*;
+        assert_eq!(table.transition(Some(Standing), Running), 0.5);
+        // transitions only go one way
+        assert_eq!(table.transition(Some(Running), Standing), 0.2);
+        // the first state has nothing to fade from
+        assert_eq!(table.transition(None, Running), 0.2);
+    }
+
+    #[test]
+    fn a_complete_table_misses_nothing() {
+        assert!(complete_table().missing_clips(has_clip).is_empty());
+    }
+
+    #[test]
+    fn missing_entries_and_clips_are_listed() {
+        let mut table = complete_table();
+        table.fallback = "T-Pose".to_string();
+        table.states.remove(&AnimationStateKind::Climbing);
+        table
+            .states
+            .get_mut(&AnimationStateKind::Dashing)
+            .unwrap()
+            .clip = "Dash".to_string();
+
+        let missing = table.missing_clips(has_clip);
+        assert_eq!(
+            missing,
+            vec![
+                "fallback clip T-Pose does not exist",
+                "Dashing plays missing clip Dash",
+                "Climbing has no entry",
+            ]
+        );
+    }
+
+    #[test]
+    fn blend_spaces_need_every_sample() {
+        let mut table = complete_table();
+        // a blend space state needs no entry
+        table.states.remove(&AnimationStateKind::Running);
+        table.blend_spaces.insert(
+            AnimationStateKind::Running,
+            vec![
+                BlendSample {
+                    clip: "Walk".to_string(),
+                    speed: 3.0,
+                },
+                BlendSample {
+                    clip: "Sprint".to_string(),
+                    speed: 14.0,
+                },
+            ],
+        );
+        table
+            .blend_spaces
+            .insert(AnimationStateKind::Crawling, Vec::new());
+
+        let missing = table.missing_clips(has_clip);
+        assert_eq!(
+            missing,
+            vec![
+                "Running blends missing clips [Sprint]",
+                "Crawling blends missing clips []",
+            ]
+        );
+    }
+
+    #[test]
+    fn root_motion_needs_a_root_bone() {
+        let mut table = complete_table();
+        table
+            .states
+            .get_mut(&AnimationStateKind::Dashing)
+            .unwrap()
+            .root_motion = true;
+        assert_eq!(
+            table.missing_clips(has_clip),
+            vec!["states use root motion, but the table has no root bone"]
+        );
+
+        table.root_bone = Some("root".to_string());
+        assert!(table.missing_clips(has_clip).is_empty());
+    }
+
+    #[test]
+    fn blended_samples_share_one_cycle() {
+        let clip = running_blend_space();
+        let weights = clip.sample_weights(5.5);
+        let speeds = clip.sample_speeds(5.5, &weights);
+
+        let cycles = clip
+            .samples
+            .iter()
+            .zip(&speeds)
+            .map(|(sample, speed)| sample.duration / speed)
+            .collect::<Vec<_>>();
+        for cycle in &cycles {
+            assert!((cycle - cycles[0]).abs() < 1e-5);
+        }
+
+        // the shared cycle blends the walk cycle of 1s at 5.5/3 and the jog one of 0.8s at 5.5/8
+        let expected = 0.5 * 1.0 / (5.5 / 3.0) + 0.5 * 0.8 / (5.5 / 8.0);
+        assert!((cycles[0] - expected).abs() < 1e-5);
+    }
+
+    #[test]
+    fn a_lone_sample_plays_at_its_own_pace() {
+        let clip = running_blend_space();
+        let weights = clip.sample_weights(8.0);
+        assert_eq!(weights, vec![0.0, 1.0, 0.0]);
+
+        let speeds = clip.sample_speeds(8.0, &weights);
+        assert!((speeds[1] - 1.0).abs() < 1e-5);
+        // the samples without weight keep the phase of the jog
+        assert!((speeds[0] - 1.0 / 0.8).abs() < 1e-5);
+    }
+
+    #[test]
+    fn standing_still_stops_the_samples() {
+        let clip = running_blend_space();
+        let weights = clip.sample_weights(0.0);
+        assert_eq!(clip.sample_speeds(0.0, &weights), vec![0.0; 3]);
+    }
+
+    #[test]
+    fn root_motion_samples_keep_the_pace_of_the_clip() {
+        let clip = ResolvedClip {
+            root_motion: true,
+            ..running_blend_space()
+        };
+        let weights = clip.sample_weights(14.0);
+        let speeds = clip.sample_speeds(14.0, &weights);
+        assert!((speeds[2] - 1.0).abs() < 1e-5);
+    }
+}
@@ -1,5 +1,6 @@
 use bevy::prelude::*;
 use bevy_tnua::math::Float;
+use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Dimensionality {
@@ -7,12 +8,20 @@ pub enum Dimensionality {
     Dim3,
 }
 
-#[derive(Component, Debug, PartialEq, Default)]
+/// How a character gets through one-way platforms, which it can always jump through from below.
+///
+/// A one-way platform with a scheme of its own is fallen through its way, whatever the scheme of
+/// the character is.
+#[derive(Component, Debug, Clone, Copy, PartialEq, Default)]
 pub enum FallingThroughControlScheme {
+    /// the platforms can not be fallen through
     JumpThroughOnly,
+    /// crouching drops through the platforms, without the fall through helper
     WithoutHelper,
+    /// pressing crouch drops through a single platform
     #[default]
     SingleFall,
+    /// holding crouch keeps falling through every platform below
     KeepFalling,
 }
 
@@ -23,4 +32,57 @@ pub struct CharacterMotionConfig {
     pub dash_distance: Float,
     pub one_way_platforms_min_proximity: Float,
     pub climb_speed: Float,
+    /// scales the walk speed while crouching
+    pub crouch_speed_factor: Float,
+}
+
+/// Player tuning, loaded from the `[player]` table of the game config
+#[derive(Resource, Debug, Clone, PartialEq, Reflect, Serialize, Deserialize)]
+#[reflect(Resource, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PlayerConfig {
+    /// the `*.character.toml` file of the character the player controls, read once the player
+    /// spawns
+    pub character: String,
+    /// running speed above which sprint effects (sound, camera) kick in
+    pub sprint_effect_speed_threshold: f32,
+    pub sound: PlayerSound,
+}
+
+impl Default for PlayerConfig {
+    fn default() -> Self {
+        Self {
+            character: "waltz/player.character.toml".to_string(),
+            sprint_effect_speed_threshold: 8.1,
+            sound: PlayerSound::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Reflect, Serialize, Deserialize)]
+#[reflect(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PlayerSound {
+    /// distance run between two footsteps
+    pub stride_length: f32,
+    /// running speed below which no footsteps are played
+    pub min_footstep_speed: f32,
+    /// footstep volume while walking, it rises to full volume at the sprint speed
+    pub walk_volume: f32,
+    /// falling speed below which a landing is silent
+    pub min_landing_speed: f32,
+    /// falling speed of a landing at full volume
+    pub max_landing_speed: f32,
+}
+
+impl Default for PlayerSound {
+    fn default() -> Self {
+        Self {
+            stride_length: 1.8,
+            min_footstep_speed: 0.5,
+            walk_volume: 0.4,
+            min_landing_speed: 3.0,
+            max_landing_speed: 20.0,
+        }
+    }
 }
@@ -0,0 +1,369 @@
+//! Characters are spawned from `*.character.toml` files, which describe their model, their
+//! collider and how Tnua floats them, so switching characters is a matter of editing a file.
+//! The player is spawned from the file named by `character` in the `[player]` table of the game
+//! config, once the config is read.
+//!
+//! The collider is a capsule standing on the origin of the character. With `auto_fit` its
+//! dimensions are only used until the model is spawned, the capsule is then fitted to the bounds
+//! of the model meshes in their bind pose. A character without a `[model]` is drawn as its
+//! capsule, without animations, and so is a character whose model fails to load. The
+//! `[inventory]` lists the weapons the character starts with.
+use avian3d::prelude::*;
+use bevy::{
+    asset::{AssetLoader, LoadContext, VisitAssetDependencies, io::Reader},
+    camera::primitives::Aabb,
+    prelude::*,
+    world_serialization::WorldAsset,
+};
+use bevy_tnua_avian3d::TnuaAvian3dSensorShape;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    character::{
+        WaltzTnuaCtrlSchemeConfig, WeaponDefinition, WeaponInventory, animating::GltfSceneHandler,
+        animation_table::AnimationTable, config::PlayerConfig, setup_character_with_entity_cmd,
+    },
+    config::{GameConfigError, apply_game_config, game_config_settled, read_toml},
+};
+
+pub(super) fn plugin(app: &mut App) {
+    app.init_asset::<CharacterDescription>()
+        .register_asset_loader(CharacterDescriptionLoader)
+        .add_systems(
+            Update,
+            (
+                spawn_player
+                    .after(apply_game_config)
+                    .run_if(game_config_settled.and(run_once)),
+                setup_described_character,
+                (fall_back_to_capsule, fit_character_collider).chain(),
+            ),
+        );
+}
+
+/// A character, loaded from a `*.character.toml` file.
+#[derive(Asset, TypePath, Debug)]
+pub struct CharacterDescription {
+    /// the character is drawn as its collider without one
+    #[dependency]
+    pub model: Option<CharacterModel>,
+    pub collider: CapsuleDescription,
+    /// the height Tnua keeps the origin of the character above the ground
+    pub float_height: f32,
+    pub sensor: SensorDescription,
+    /// the character carries no weapons without one
+    #[dependency]
+    pub inventory: Option<InventoryDescription>,
+}
+
+/// The animated model of a character.
+#[derive(VisitAssetDependencies, Debug)]
+pub struct CharacterModel {
+    #[dependency]
+    pub scene: Handle<WorldAsset>,
+    /// the glTF of the scene, its named animations are played by the animation table
+    #[dependency]
+    pub gltf: Handle<Gltf>,
+    #[dependency]
+    pub animation_table: Handle<AnimationTable>,
+    /// fits the collider to the model once it is spawned
+    pub auto_fit: bool,
+}
+
+/// The weapon slots of a character and the weapons it starts with.
+#[derive(VisitAssetDependencies, Debug)]
+pub struct InventoryDescription {
+    pub slots: usize,
+    /// in slot order
+    #[dependency]
+    pub weapons: Vec<Handle<WeaponDefinition>>,
+}
+
+impl InventoryDescription {
+    fn inventory(&self) -> WeaponInventory {
+        let mut inventory = WeaponInventory::new(self.slots);
+        for (slot, weapon) in self.weapons.iter().enumerate() {
+            inventory.set_slot(slot, Some(weapon.clone()));
+        }
+        inventory
+    }
+}
+
+/// A capsule standing on the origin of the character, its bottom touches the origin.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CapsuleDescription {
+    pub radius: f32,
+    /// from the bottom to the top of the capsule
+    pub height: f32,
+}
+
+impl CapsuleDescription {
+    fn radius(&self) -> f32 {
+        self.radius.min(self.height * 0.5)
+    }
+
+    fn collider(&self) -> Collider {
+        let radius = self.radius();
+        Collider::capsule_endpoints(
+            radius,
+            Vec3::Y * radius,
+            Vec3::Y * (self.height - radius).max(radius),
+        )
+    }
+
+    /// The mesh of the capsule with its transform, relative to the character.
+    fn mesh(&self) -> (Capsule3d, Transform) {
+        let radius = self.radius();
+        let height = self.height.max(radius * 2.0);
+        (
+            Capsule3d::new(radius, height - radius * 2.0),
+            Transform::from_xyz(0.0, height * 0.5, 0.0),
+        )
+    }
+}
+
+/// The cylinder Tnua casts down to find the ground, it should be a little narrower than the
+/// collider so it does not hit the walls the character leans on.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SensorDescription {
+    pub radius: f32,
+    #[serde(default)]
+    pub height: f32,
+}
+
+/// The content of a `*.character.toml` file.
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CharacterFile {
+    #[serde(default)]
+    model: Option<ModelFile>,
+    collider: CapsuleDescription,
+    float_height: f32,
+    sensor: SensorDescription,
+    #[serde(default)]
+    inventory: Option<InventoryFile>,
+}
+
+/// The `[model]` of a `*.character.toml` file, `gltf` is the path of the glTF file and
+/// `animation_table` the path of its `*.anim.ron` file.
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ModelFile {
+    gltf: String,
+    animation_table: String,
+    #[serde(default)]
+    auto_fit: bool,
+}
+
+/// The `[inventory]` of a `*.character.toml` file, `weapons` are the paths of the
+/// `*.weapon.toml` files in slot order.
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct InventoryFile {
+    slots: usize,
+    #[serde(default)]
+    weapons: Vec<String>,
+}
+
+#[derive(Default, TypePath)]
+struct CharacterDescriptionLoader;
+
+impl AssetLoader for CharacterDescriptionLoader {
+    type Asset = CharacterDescription;
+    type Settings = ();
+    type Error = GameConfigError;
+
+    async fn load(
+        &self,
+        reader: &mut dyn Reader,
+        _settings: &(),
+        load_context: &mut LoadContext<'_>,
+    ) -> Result<Self::Asset, Self::Error> {
+        let file: CharacterFile = read_toml(reader).await?;
+
+        Ok(CharacterDescription {
+            model: file.model.map(|model| CharacterModel {
+                scene: load_context.load(GltfAssetLabel::Scene(0).from_asset(model.gltf.clone())),
+                gltf: load_context.load(model.gltf),
+                animation_table: load_context.load(model.animation_table),
+                auto_fit: model.auto_fit,
+            }),
+            collider: file.collider,
+            float_height: file.float_height,
+            sensor: file.sensor,
+            inventory: file.inventory.map(|inventory| InventoryDescription {
+                slots: inventory.slots,
+                weapons: inventory
+                    .weapons
+                    .into_iter()
+                    .map(|weapon| load_context.load(weapon))
+                    .collect(),
+            }),
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["character.toml"]
+    }
+}
+
+/// A player character waiting for its description to load, it is set up once the description is.
+#[derive(Component)]
+pub struct DescribedCharacter(pub Handle<CharacterDescription>);
+
+/// Fits the collider of the character to its model once the model meshes are spawned, the
+/// sensor is narrowed if it gets wider than the collider. The collider is kept as is if the model
+/// fails to load.
+#[derive(Component)]
+struct FitCollider(SensorDescription);
+
+/// The capsule a character is drawn as if its model fails to load, removed once the model loads.
+#[derive(Component)]
+struct CapsuleFallback(CapsuleDescription);
+
+fn spawn_player(config: Res<PlayerConfig>, mut commands: Commands, asset_server: Res<AssetServer>) {
+    info!("spawn the player as {}", config.character);
+    commands.spawn((
+        Name::new("Player"),
+        Transform::default(),
+        Visibility::default(),
+        DescribedCharacter(asset_server.load(config.character.clone())),
+    ));
+}
+
+/// Draws the character as its capsule, a child mesh of the character.
+fn spawn_capsule_mesh(
+    cmd: &mut EntityCommands,
+    capsule: &CapsuleDescription,
+    meshes: &mut Assets<Mesh>,
+    materials: &mut Assets<StandardMaterial>,
+) {
+    let (capsule, transform) = capsule.mesh();
+    cmd.with_child((
+        Mesh3d(meshes.add(capsule)),
+        MeshMaterial3d(materials.add(Color::srgb(0.8, 0.7, 0.6))),
+        transform,
+    ));
+}
+
+fn setup_described_character(
+    characters: Query<(Entity, &DescribedCharacter)>,
+    descriptions: Res<Assets<CharacterDescription>>,
+    mut ctrl_scheme_cfg_assets: ResMut<Assets<WaltzTnuaCtrlSchemeConfig>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut commands: Commands,
+) {
+    for (entity, DescribedCharacter(handle)) in &characters {
+        let Some(description) = descriptions.get(handle) else {
+            continue;
+        };
+
+        let mut cmd = commands.entity(entity);
+        cmd.remove::<DescribedCharacter>().insert((
+            description.collider.collider(),
+            TnuaAvian3dSensorShape(Collider::cylinder(
+                description.sensor.radius,
+                description.sensor.height,
+            )),
+        ));
+        if let Some(inventory) = &description.inventory {
+            cmd.insert(inventory.inventory());
+        }
+        if let Some(model) = &description.model {
+            cmd.insert((
+                WorldAssetRoot(model.scene.clone()),
+                GltfSceneHandler {
+                    names_from: model.gltf.clone(),
+                    animation_table: model.animation_table.clone(),
+                },
+                CapsuleFallback(description.collider.clone()),
+            ));
+            if model.auto_fit {
+                cmd.insert(FitCollider(description.sensor.clone()));
+            }
+        } else {
+            spawn_capsule_mesh(&mut cmd, &description.collider, &mut meshes, &mut materials);
+        }
+
+        setup_character_with_entity_cmd(cmd, description.float_height, &mut ctrl_scheme_cfg_assets);
+    }
+}
+
+/// Draws the character as its capsule once its model fails to load, its collider is kept.
+fn fall_back_to_capsule(
+    characters: Query<(Entity, &WorldAssetRoot, &CapsuleFallback)>,
+    asset_server: Res<AssetServer>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut commands: Commands,
+) {
+    for (character, WorldAssetRoot(scene), CapsuleFallback(capsule)) in &characters {
+        let state = asset_server.load_state(scene);
+        if state.is_loaded() {
+            commands.entity(character).remove::<CapsuleFallback>();
+        } else if state.is_failed() {
+            warn!("the model of {character} failed to load, it is drawn as its capsule");
+            let mut cmd = commands.entity(character);
+            cmd.remove::<(
+                WorldAssetRoot,
+                GltfSceneHandler,
+                CapsuleFallback,
+                FitCollider,
+            )>();
+            spawn_capsule_mesh(&mut cmd, capsule, &mut meshes, &mut materials);
+        }
+    }
+}
+
+/// Fits a capsule to the bounds of the model meshes, relative to the character. The radius comes
+/// from the narrower horizontal extent, so the arms of a T-pose do not widen it.
+fn fit_character_collider(
+    characters: Query<(Entity, &GlobalTransform, &FitCollider)>,
+    children: Query<&Children>,
+    meshes: Query<(&Aabb, &GlobalTransform), With<Mesh3d>>,
+    mut commands: Commands,
+) {
+    for (character, character_transform, FitCollider(sensor)) in &characters {
+        let to_character = character_transform.affine().inverse();
+
+        let mut min = Vec3::INFINITY;
+        let mut max = Vec3::NEG_INFINITY;
+        for (aabb, mesh_transform) in meshes.iter_many(children.iter_descendants(character)) {
+            let to_character = to_character * mesh_transform.affine();
+            let (aabb_min, aabb_max) = (Vec3::from(aabb.min()), Vec3::from(aabb.max()));
+            for corner in 0..8 {
+                let point = Vec3::select(
+                    BVec3::new(corner & 1 != 0, corner & 2 != 0, corner & 4 != 0),
+                    aabb_max,
+                    aabb_min,
+                );
+                let point = to_character.transform_point3(point);
+                min = min.min(point);
+                max = max.max(point);
+            }
+        }
+
+        // the model is not spawned yet
+        if min.x > max.x {
+            continue;
+        }
+
+        let size = max - min;
+        let center = (min + max) * 0.5;
+        let radius = (size.x.min(size.z) * 0.5).min(size.y * 0.5).max(0.01);
+        let bottom = Vec3::new(center.x, min.y + radius, center.z);
+        let top = Vec3::new(center.x, (max.y - radius).max(min.y + radius), center.z);
+        debug!("fitted the collider of {character} to radius {radius}, from {bottom} to {top}");
+
+        commands.entity(character).remove::<FitCollider>().insert((
+            Collider::capsule_endpoints(radius, bottom, top),
+            TnuaAvian3dSensorShape(Collider::cylinder(
+                sensor.radius.min(radius * 0.98),
+                sensor.height,
+            )),
+        ));
+    }
+}
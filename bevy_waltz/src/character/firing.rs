@@ -0,0 +1,531 @@
+//! Fires the equipped weapon, by a ray or by a projectile depending on its [`WeaponDelivery`].
+//!
+//! The pipeline only depends on avian and on the [`EquippedWeapon`] of the holder, so it also runs
+//! in a headless app made of `MinimalPlugins`, avian's `PhysicsPlugins` and [`WeaponFiringPlugin`].
+use avian3d::prelude::*;
+use bevy::prelude::*;
+
+use crate::{
+    camera::{CameraShakeSource, ShakeCamera, config::CollisionLayer},
+    character::{
+        WaltzPlayer, character_facing,
+        weapon::{WeaponDefinition, WeaponDelivery, WeaponStats},
+    },
+    gp::DamageKind,
+};
+
+/// Height of the shot origin above the holder when it has no muzzle bone, e.g. the demo capsule.
+const FALLBACK_MUZZLE_HEIGHT: f32 = 1.5;
+
+pub struct WeaponFiringPlugin;
+
+impl Plugin for WeaponFiringPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<EquippedWeapon>()
+            .register_type::<Projectile>()
+            .add_observer(fire_weapon)
+            .add_observer(reload_weapon)
+            .add_systems(Update, tick_weapons)
+            .add_systems(FixedUpdate, sweep_projectiles);
+    }
+}
+
+/// The weapon a character holds, with its ammo and fire rate state.
+#[derive(Component, Debug, Clone, Reflect)]
+#[reflect(Component)]
+pub struct EquippedWeapon {
+    pub stats: WeaponStats,
+    /// name of the bone shots start from
+    pub muzzle_bone: String,
+    /// rounds left in the magazine
+    pub rounds: u32,
+    /// seconds until the next shot is allowed
+    cooldown: f32,
+    /// seconds until the running reload finishes
+    reload: Option<f32>,
+    /// shots fired so far, drives the spread pattern
+    shots: u32,
+    /// the muzzle bone entity, looked up on the first shot
+    muzzle: Option<Entity>,
+}
+
+impl EquippedWeapon {
+    pub fn new(definition: &WeaponDefinition, rounds: u32) -> Self {
+        Self::from_stats(
+            definition.stats.clone(),
+            definition.attach_bone.clone(),
+            rounds,
+        )
+    }
+
+    pub fn from_stats(stats: WeaponStats, muzzle_bone: impl Into<String>, rounds: u32) -> Self {
+        Self {
+            stats,
+            muzzle_bone: muzzle_bone.into(),
+            rounds,
+            cooldown: 0.0,
+            reload: None,
+            shots: 0,
+            muzzle: None,
+        }
+    }
+
+    pub fn is_reloading(&self) -> bool {
+        self.reload.is_some()
+    }
+
+    pub fn can_fire(&self) -> bool {
+        self.cooldown <= 0.0 && self.reload.is_none() && self.rounds > 0
+    }
+
+    /// Starts a reload unless one is running or the magazine is full.
+    fn start_reload(&mut self) -> bool {
+        if self.reload.is_some() || self.rounds >= self.stats.magazine_size {
+            return false;
+        }
+
+        self.reload = Some(self.stats.reload_time);
+        true
+    }
+}
+
+/// Fires the weapon equipped by `entity` once, if the fire rate and the ammo allow it.
+/// The shot aims at `target`, or straight ahead of the holder without one.
+#[derive(Debug, Clone, Copy, PartialEq, EntityEvent)]
+pub struct FireWeapon {
+    entity: Entity,
+    target: Option<Vec3>,
+}
+
+impl FireWeapon {
+    pub fn new(entity: Entity, target: Option<Vec3>) -> Self {
+        Self { entity, target }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, EntityEvent)]
+pub struct ReloadWeapon {
+    entity: Entity,
+}
+
+impl ReloadWeapon {
+    pub fn new(entity: Entity) -> Self {
+        Self { entity }
+    }
+}
+
+/// `entity` fired a shot, after the fire rate and the ammo allowed it.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, EntityEvent)]
+pub struct WeaponFired {
+    pub entity: Entity,
+}
+
+/// A shot hit `entity`, it propagates up the hierarchy so a hit on a child collider reaches
+/// the body owning it.
+#[derive(Debug, Clone, PartialEq, EntityEvent)]
+#[entity_event(propagate, auto_propagate)]
+pub struct Hit {
+    pub entity: Entity,
+    /// the holder of the weapon
+    pub source: Entity,
+    pub damage: f32,
+    pub kind: DamageKind,
+    pub point: Vec3,
+    pub normal: Vec3,
+}
+
+/// A shot on its way, moved by the physics and swept against the colliders every tick.
+#[derive(Component, Debug, Clone, Reflect)]
+#[reflect(Component)]
+pub struct Projectile {
+    pub source: Entity,
+    pub damage: f32,
+    pub kind: DamageKind,
+    radius: f32,
+    /// seconds until the projectile is despawned
+    lifetime: f32,
+    /// translation on the last sweep
+    previous: Vec3,
+}
+
+/// Shots pass through sensors like vines.
+const SHOT_LAYERS: [CollisionLayer; 3] = [
+    CollisionLayer::Character,
+    CollisionLayer::Terrain,
+    CollisionLayer::CameraObstacle,
+];
+
+/// Deviates `direction` by up to `spread` degrees. The deviations follow a golden angle spiral
+/// over the shot count, so they cover the cone evenly, the first shot is always accurate and the
+/// pattern is reproducible.
+fn spread_direction(direction: Dir3, spread: f32, shot: u32) -> Dir3 {
+    const GOLDEN_ANGLE: f32 = 2.399_963;
+    const GOLDEN_RATIO_FRACT: f32 = 0.618_034;
+
+    if spread <= 0.0 {
+        return direction;
+    }
+
+    let shot = shot as f32;
+    let deviation = (shot * GOLDEN_RATIO_FRACT).fract().sqrt() * spread.to_radians();
+    let angle = shot * GOLDEN_ANGLE;
+
+    let (right, up) = direction.any_orthonormal_pair();
+    let axis = right * angle.cos() + up * angle.sin();
+
+    Dir3::new(Quat::from_axis_angle(axis, deviation) * direction.as_vec3()).unwrap_or(direction)
+}
+
+fn fire_weapon(
+    fire: On<FireWeapon>,
+    mut commands: Commands,
+    mut holders: Query<(&mut EquippedWeapon, &GlobalTransform, Has<WaltzPlayer>)>,
+    bones: Query<(&Name, &GlobalTransform)>,
+    children: Query<&Children>,
+    spatial_query: SpatialQuery,
+) {
+    let FireWeapon { entity, target } = *fire.event();
+    let Ok((mut weapon, holder_transform, is_player)) = holders.get_mut(entity) else {
+        return;
+    };
+
+    if weapon.rounds == 0 {
+        if weapon.start_reload() {
+            debug!("{entity} is out of ammo, reload");
+        }
+        return;
+    }
+
+    if !weapon.can_fire() {
+        return;
+    }
+
+    if weapon.muzzle.is_none() {
+        let muzzle = children.iter_descendants(entity).find(|bone| {
+            bones
+                .get(*bone)
+                .is_ok_and(|(name, _)| name.as_str() == weapon.muzzle_bone)
+        });
+        weapon.muzzle = muzzle;
+    }
+
+    let origin = weapon
+        .muzzle
+        .and_then(|muzzle| bones.get(muzzle).ok())
+        .map(|(_, transform)| transform.translation())
+        .unwrap_or(holder_transform.translation() + Vec3::Y * FALLBACK_MUZZLE_HEIGHT);
+
+    let aim = target
+        .and_then(|target| Dir3::new(target - origin).ok())
+        .unwrap_or_else(|| character_facing(&holder_transform.compute_transform()));
+    let direction = spread_direction(aim, weapon.stats.spread, weapon.shots);
+
+    weapon.rounds -= 1;
+    weapon.shots = weapon.shots.wrapping_add(1);
+    weapon.cooldown = 1.0 / weapon.stats.fire_rate.max(1e-3);
+
+    let (damage, kind) = (weapon.stats.damage, weapon.stats.damage_kind);
+    match weapon.stats.delivery {
+        WeaponDelivery::Hitscan { range } => {
+            let filter =
+                SpatialQueryFilter::from_mask(SHOT_LAYERS).with_excluded_entities([entity]);
+            if let Some(hit) = spatial_query.cast_ray(origin, direction, range, true, &filter) {
+                commands.trigger(Hit {
+                    entity: hit.entity,
+                    source: entity,
+                    damage,
+                    kind,
+                    point: origin + direction * hit.distance,
+                    normal: hit.normal,
+                });
+            }
+        }
+        WeaponDelivery::Projectile {
+            speed,
+            radius,
+            gravity_scale,
+            lifetime,
+        } => {
+            commands.spawn((
+                Name::new("projectile"),
+                Projectile {
+                    source: entity,
+                    damage,
+                    kind,
+                    radius,
+                    lifetime,
+                    previous: origin,
+                },
+                Transform::from_translation(origin),
+                RigidBody::Dynamic,
+                Collider::sphere(radius),
+                // the sweep detects the hits, the body itself must not bounce off
+                Sensor,
+                CollisionLayers::new(CollisionLayer::Sensor, SHOT_LAYERS),
+                GravityScale(gravity_scale),
+                LinearVelocity(direction * speed),
+            ));
+        }
+    }
+
+    commands.trigger(WeaponFired { entity });
+    if is_player {
+        commands.trigger(ShakeCamera::new(
+            CameraShakeSource::Weapon,
+            weapon.stats.recoil,
+        ));
+    }
+}
+
+fn reload_weapon(reload: On<ReloadWeapon>, mut holders: Query<&mut EquippedWeapon>) {
+    let entity = reload.event().entity;
+    if let Ok(mut weapon) = holders.get_mut(entity) {
+        if weapon.start_reload() {
+            debug!("{entity} reloads");
+        }
+    }
+}
+
+fn tick_weapons(time: Res<Time>, mut weapons: Query<&mut EquippedWeapon>) {
+    let dt = time.delta_secs();
+
+    for mut weapon in &mut weapons {
+        if weapon.cooldown > 0.0 {
+            weapon.cooldown = (weapon.cooldown - dt).max(0.0);
+        }
+
+        if let Some(remaining) = weapon.reload {
+            if remaining <= dt {
+                weapon.rounds = weapon.stats.magazine_size;
+                weapon.reload = None;
+            } else {
+                weapon.reload = Some(remaining - dt);
+            }
+        }
+    }
+}
+
+/// Sweeps every projectile from its last position to the current one, so fast projectiles can
+/// not tunnel through thin colliders.
+fn sweep_projectiles(
+    mut commands: Commands,
+    mut projectiles: Query<(Entity, &Transform, &mut Projectile)>,
+    spatial_query: SpatialQuery,
+    time: Res<Time>,
+) {
+    for (entity, transform, mut projectile) in &mut projectiles {
+        let current = transform.translation;
+
+        if let Ok((direction, distance)) = Dir3::new_and_length(current - projectile.previous) {
+            let filter = SpatialQueryFilter::from_mask(SHOT_LAYERS)
+                .with_excluded_entities([entity, projectile.source]);
+            let config = ShapeCastConfig {
+                max_distance: distance,
+                ..Default::default()
+            };
+
+            if let Some(hit) = spatial_query.cast_shape(
+                &Collider::sphere(projectile.radius),
+                projectile.previous,
+                Quat::IDENTITY,
+                direction,
+                &config,
+                &filter,
+            ) {
+                commands.trigger(Hit {
+                    entity: hit.entity,
+                    source: projectile.source,
+                    damage: projectile.damage,
+                    kind: projectile.kind,
+                    point: hit.point1,
+                    normal: hit.normal1,
+                });
+                commands.entity(entity).despawn();
+                continue;
+            }
+        }
+
+        projectile.previous = current;
+        projectile.lifetime -= time.delta_secs();
+        if projectile.lifetime <= 0.0 {
+            commands.entity(entity).despawn();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use bevy::time::TimeUpdateStrategy;
+
+    use super::*;
+
+    /// The entities that received a [`Hit`] and the shots fired, in order.
+    #[derive(Resource, Default)]
+    struct Shots {
+        hits: Vec<Entity>,
+        fired: u32,
+    }
+
+    fn app() -> App {
+        let mut app = App::new();
+        app.add_plugins((
+            MinimalPlugins,
+            TransformPlugin,
+            AssetPlugin::default(),
+            PhysicsPlugins::default(),
+            WeaponFiringPlugin,
+        ))
+        .init_asset::<Mesh>()
+        // one fixed step per update
+        .insert_resource(TimeUpdateStrategy::ManualDuration(Duration::from_secs_f64(
+            1.0 / 64.0,
+        )))
+        .init_resource::<Shots>()
+        .add_observer(|_: On<WeaponFired>, mut shots: ResMut<Shots>| shots.fired += 1);
+        app
+    }
+
+    fn stats(delivery: WeaponDelivery) -> WeaponStats {
+        WeaponStats {
+            fire_rate: 2.0,
+            automatic: false,
+            magazine_size: 2,
+            damage: 10.0,
+            damage_kind: DamageKind::Physical,
+            spread: 0.0,
+            reload_time: 0.25,
+            recoil: 0.0,
+            delivery,
+        }
+    }
+
+    fn spawn_holder(app: &mut App, delivery: WeaponDelivery) -> Entity {
+        app.world_mut()
+            .spawn((
+                Transform::default(),
+                EquippedWeapon::from_stats(stats(delivery), "muzzle", 2),
+            ))
+            .id()
+    }
+
+    /// Records the hits that reach `entity`, directly or through its children.
+    fn record_hits(app: &mut App, entity: Entity) {
+        app.world_mut()
+            .entity_mut(entity)
+            .observe(|hit: On<Hit>, mut shots: ResMut<Shots>| shots.hits.push(hit.entity));
+    }
+
+    fn fire(app: &mut App, holder: Entity, target: Vec3) {
+        app.world_mut()
+            .trigger(FireWeapon::new(holder, Some(target)));
+        app.world_mut().flush();
+    }
+
+    fn run(app: &mut App, seconds: f32) {
+        for _ in 0..(seconds * 64.0).ceil() as u32 {
+            app.update();
+        }
+    }
+
+    #[test]
+    fn hitscan_hits_the_owner_of_the_collider() {
+        let mut app = app();
+        let holder = spawn_holder(&mut app, WeaponDelivery::Hitscan { range: 50.0 });
+        let target = Vec3::new(0.0, FALLBACK_MUZZLE_HEIGHT, 10.0);
+        let owner = app
+            .world_mut()
+            .spawn((Transform::from_translation(target), RigidBody::Static))
+            .with_child((
+                Collider::cuboid(2.0, 2.0, 0.5),
+                CollisionLayers::new(CollisionLayer::Character, LayerMask::ALL),
+            ))
+            .id();
+        record_hits(&mut app, owner);
+        // the colliders are queried once the physics ran
+        run(&mut app, 0.1);
+
+        fire(&mut app, holder, target);
+
+        assert_eq!(app.world().resource::<Shots>().hits, [owner]);
+    }
+
+    #[test]
+    fn fire_rate_and_ammo_gate_the_shots() {
+        let mut app = app();
+        let holder = spawn_holder(&mut app, WeaponDelivery::Hitscan { range: 50.0 });
+        run(&mut app, 0.1);
+        let fired = |app: &App| app.world().resource::<Shots>().fired;
+        let weapon = |app: &App| app.world().get::<EquippedWeapon>(holder).unwrap().clone();
+
+        fire(&mut app, holder, Vec3::Z);
+        fire(&mut app, holder, Vec3::Z);
+        assert_eq!(
+            fired(&app),
+            1,
+            "the fire rate allows one shot per half second"
+        );
+
+        run(&mut app, 0.6);
+        fire(&mut app, holder, Vec3::Z);
+        assert_eq!(fired(&app), 2);
+        assert_eq!(weapon(&app).rounds, 0);
+
+        run(&mut app, 0.6);
+        fire(&mut app, holder, Vec3::Z);
+        assert_eq!(fired(&app), 2, "an empty magazine does not fire");
+        assert!(weapon(&app).is_reloading(), "an empty magazine reloads");
+
+        run(&mut app, 0.3);
+        assert_eq!(weapon(&app).rounds, 2);
+        fire(&mut app, holder, Vec3::Z);
+        assert_eq!(fired(&app), 3);
+
+        run(&mut app, 0.6);
+        app.world_mut().trigger(ReloadWeapon::new(holder));
+        app.world_mut().flush();
+        fire(&mut app, holder, Vec3::Z);
+        assert_eq!(fired(&app), 3, "a running reload blocks the shots");
+
+        run(&mut app, 0.3);
+        assert_eq!(weapon(&app).rounds, 2);
+    }
+
+    #[test]
+    fn projectiles_do_not_tunnel_through_thin_walls() {
+        let mut app = app();
+        let holder = spawn_holder(
+            &mut app,
+            WeaponDelivery::Projectile {
+                // several meters per step, far more than the wall is thick
+                speed: 500.0,
+                radius: 0.05,
+                gravity_scale: 0.0,
+                lifetime: 1.0,
+            },
+        );
+        let wall = app
+            .world_mut()
+            .spawn((
+                Transform::from_xyz(0.0, FALLBACK_MUZZLE_HEIGHT, 5.0),
+                RigidBody::Static,
+                Collider::cuboid(4.0, 4.0, 0.02),
+                CollisionLayers::new(CollisionLayer::Terrain, LayerMask::ALL),
+            ))
+            .id();
+        record_hits(&mut app, wall);
+        run(&mut app, 0.1);
+
+        fire(
+            &mut app,
+            holder,
+            Vec3::new(0.0, FALLBACK_MUZZLE_HEIGHT, 10.0),
+        );
+        run(&mut app, 0.2);
+
+        assert_eq!(app.world().resource::<Shots>().hits, [wall]);
+        let mut projectiles = app.world_mut().query::<&Projectile>();
+        assert_eq!(projectiles.iter(app.world()).count(), 0);
+    }
+}
@@ -0,0 +1,322 @@
+//! Foot IK keeps the feet of the character on uneven ground, like slopes and stairs.
+//!
+//! After the animations are applied, the ground is probed under every foot. The hips are lowered
+//! to let the lowest foot reach its ground, each leg is bent by a two-bone solver so its foot
+//! lands on its own ground, and the foot is turned to the ground normal. The result is blended by
+//! the weight of the animation state, the bones are found by the names of the [`FootIkConfig`].
+use std::collections::HashMap;
+
+use avian3d::prelude::{SpatialQuery, SpatialQueryFilter};
+use bevy::{
+    animation::AnimationSystems, ecs::query::QueryFilter, prelude::*, transform::TransformSystems,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    camera::config::CollisionLayer,
+    character::{animating::AnimationsHandler, animation_table::AnimationStateKind, root_motion},
+};
+
+pub(super) fn plugin(app: &mut App) {
+    app.add_systems(
+        PostUpdate,
+        solve_foot_ik
+            .after(AnimationSystems)
+            .after(root_motion::extract_root_motion)
+            .before(TransformSystems::Propagate),
+    );
+}
+
+fn default_probe_height() -> f32 {
+    0.5
+}
+
+fn default_probe_depth() -> f32 {
+    0.6
+}
+
+fn default_max_hip_drop() -> f32 {
+    0.4
+}
+
+fn default_blend_speed() -> f32 {
+    8.0
+}
+
+/// A leg chain, the lower bone is a child of the upper bone and the foot a child of the lower one.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LegBones {
+    pub upper: String,
+    pub lower: String,
+    pub foot: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FootIkConfig {
+    /// the bone lowered to let the legs reach the ground below the character
+    pub hips: String,
+    pub legs: Vec<LegBones>,
+    /// how far above the ground of the character the ground under a foot is probed
+    #[serde(default = "default_probe_height")]
+    pub probe_height: f32,
+    /// how far below the ground of the character the ground under a foot is probed
+    #[serde(default = "default_probe_depth")]
+    pub probe_depth: f32,
+    #[serde(default = "default_max_hip_drop")]
+    pub max_hip_drop: f32,
+    /// how fast the IK weight and the hips follow their target, per second
+    #[serde(default = "default_blend_speed")]
+    pub blend_speed: f32,
+    /// the weight of the IK in each state, the states left out have no IK
+    #[serde(default)]
+    pub state_weights: HashMap<AnimationStateKind, f32>,
+}
+
+impl FootIkConfig {
+    /// Finds the bones below the animation player, the IK is left out if one is missing.
+    pub fn rig(
+        &self,
+        player: Entity,
+        children: &Query<&Children>,
+        bones: &Query<(&Name, &Transform)>,
+    ) -> Option<FootIkRig> {
+        let find = |name: &str| {
+            let bone = children.iter_descendants(player).find(|entity| {
+                bones
+                    .get(*entity)
+                    .is_ok_and(|(bone_name, _)| bone_name.as_str() == name)
+            });
+            if bone.is_none() {
+                error!("foot IK is disabled, bone {name} does not exist");
+            }
+            bone
+        };
+
+        let hips = find(&self.hips)?;
+        let legs = self
+            .legs
+            .iter()
+            .map(|leg| {
+                Some(LegChain {
+                    upper: find(&leg.upper)?,
+                    lower: find(&leg.lower)?,
+                    foot: find(&leg.foot)?,
+                })
+            })
+            .collect::<Option<Vec<_>>>()?;
+
+        Some(FootIkRig {
+            hips,
+            legs,
+            config: self.clone(),
+            weight: 0.0,
+            hip_offset: 0.0,
+            hips_shifted: None,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct LegChain {
+    upper: Entity,
+    lower: Entity,
+    foot: Entity,
+}
+
+/// The leg bones of a character with the blend state of its foot IK.
+#[derive(Component, Debug)]
+pub struct FootIkRig {
+    hips: Entity,
+    legs: Vec<LegChain>,
+    config: FootIkConfig,
+    weight: f32,
+    /// vertical offset of the hips, in world space
+    hip_offset: f32,
+    /// the hips translation written on the last frame with the local shift it contains, the shift
+    /// is taken back when no clip animated the hips since
+    hips_shifted: Option<(Vec3, Vec3)>,
+}
+
+/// The global transform from the local transforms of the entity and its ancestors, the
+/// propagation of this frame did not run yet.
+pub(super) fn global_transform<F: QueryFilter>(
+    entity: Entity,
+    transforms: &Query<(&mut Transform, Option<&ChildOf>), F>,
+) -> Option<GlobalTransform> {
+    let (transform, child_of) = transforms.get(entity).ok()?;
+    match child_of {
+        Some(child_of) => {
+            global_transform(child_of.parent(), transforms).map(|parent| parent * *transform)
+        }
+        None => Some(GlobalTransform::from(*transform)),
+    }
+}
+
+/// Bends the chain `upper`, `knee`, `foot` to reach `target`, keeping the knee on the side it
+/// already bends to. Returns the new knee and the reached foot position.
+fn solve_two_bone(upper: Vec3, knee: Vec3, foot: Vec3, target: Vec3) -> (Vec3, Vec3) {
+    let upper_length = upper.distance(knee);
+    let lower_length = knee.distance(foot);
+    let to_target = target - upper;
+    let Ok(direction) = Dir3::new(to_target) else {
+        return (knee, foot);
+    };
+
+    let reach = to_target
+        .length()
+        .clamp(1e-3, (upper_length + lower_length - 1e-3).max(1e-3));
+    let bend = (knee - upper)
+        .reject_from_normalized(*direction)
+        .try_normalize()
+        .unwrap_or_else(|| direction.any_orthonormal_vector());
+
+    let cos = ((upper_length.powi(2) + reach.powi(2) - lower_length.powi(2))
+        / (2.0 * upper_length * reach).max(1e-6))
+    .clamp(-1.0, 1.0);
+    let sin = (1.0 - cos * cos).sqrt();
+
+    let knee = upper + *direction * (upper_length * cos) + bend * (upper_length * sin);
+    (knee, upper + *direction * reach)
+}
+
+pub(super) fn solve_foot_ik(
+    mut characters: Query<(Entity, &mut FootIkRig, &AnimationsHandler)>,
+    mut transforms: Query<(&mut Transform, Option<&ChildOf>)>,
+    spatial_query: SpatialQuery,
+    time: Res<Time>,
+) {
+    let dt = time.delta_secs();
+
+    for (character, mut rig, handler) in &mut characters {
+        let rig = &mut *rig;
+        if let Some((written, shift)) = rig.hips_shifted.take() {
+            if let Ok((mut hips, _)) = transforms.get_mut(rig.hips) {
+                if hips.translation == written {
+                    hips.translation -= shift;
+                }
+            }
+        }
+
+        let config = &rig.config;
+
+        let target_weight = handler
+            .state
+            .and_then(|state| config.state_weights.get(&state))
+            .copied()
+            .unwrap_or_default();
+        let blend = (config.blend_speed * dt).min(1.0);
+        rig.weight = rig.weight.lerp(target_weight, blend);
+        if rig.weight < 1e-3 && rig.hip_offset.abs() < 1e-3 {
+            continue;
+        }
+
+        let Some(ground) =
+            global_transform(character, &transforms).map(|root| root.translation().y)
+        else {
+            continue;
+        };
+        let filter =
+            SpatialQueryFilter::from_mask([CollisionLayer::Terrain, CollisionLayer::FallThrough])
+                .with_excluded_entities([character]);
+
+        // the ground height under every foot relative to the ground of the character
+        let mut legs = Vec::with_capacity(rig.legs.len());
+        for leg in &rig.legs {
+            let (Some(upper), Some(lower), Some(foot)) = (
+                global_transform(leg.upper, &transforms),
+                global_transform(leg.lower, &transforms),
+                global_transform(leg.foot, &transforms),
+            ) else {
+                continue;
+            };
+
+            let foot_position = foot.translation();
+            let origin = Vec3::new(
+                foot_position.x,
+                ground + config.probe_height,
+                foot_position.z,
+            );
+            let hit = spatial_query.cast_ray(
+                origin,
+                Dir3::NEG_Y,
+                config.probe_height + config.probe_depth,
+                true,
+                &filter,
+            );
+            let (offset, normal) = hit.map_or((0.0, Vec3::Y), |hit| {
+                (config.probe_height - hit.distance, hit.normal)
+            });
+            legs.push((leg, upper, lower, foot, offset, normal));
+        }
+
+        // the hips drop for the lowest foot, the legs bend for the higher ones
+        let lowest = legs
+            .iter()
+            .map(|(.., offset, _)| *offset)
+            .fold(0.0_f32, f32::min);
+        let target_hip_offset = lowest.max(-config.max_hip_drop) * rig.weight;
+        rig.hip_offset = rig.hip_offset.lerp(target_hip_offset, blend);
+        let hip_shift = Vec3::Y * rig.hip_offset;
+
+        if let Some(hips_parent) = transforms
+            .get(rig.hips)
+            .ok()
+            .and_then(|(_, child_of)| child_of)
+            .and_then(|child_of| global_transform(child_of.parent(), &transforms))
+        {
+            let local_shift = hips_parent.affine().inverse().transform_vector3(hip_shift);
+            if let Ok((mut hips, _)) = transforms.get_mut(rig.hips) {
+                hips.translation += local_shift;
+                rig.hips_shifted = Some((hips.translation, local_shift));
+            }
+        }
+
+        for (leg, upper, lower, foot, offset, normal) in legs {
+            let Some(upper_parent) = transforms
+                .get(leg.upper)
+                .ok()
+                .and_then(|(_, child_of)| child_of)
+                .and_then(|child_of| global_transform(child_of.parent(), &transforms))
+            else {
+                continue;
+            };
+
+            // the chain moved down with the hips, the foot keeps its height above its own ground
+            let upper_position = upper.translation() + hip_shift;
+            let knee_position = lower.translation() + hip_shift;
+            let foot_position = foot.translation() + hip_shift;
+            let target = foot.translation() + Vec3::Y * offset;
+            let target = foot_position.lerp(target, rig.weight);
+
+            let (knee, reached) =
+                solve_two_bone(upper_position, knee_position, foot_position, target);
+
+            let upper_turn = Quat::from_rotation_arc(
+                (knee_position - upper_position).normalize(),
+                (knee - upper_position).normalize(),
+            );
+            let turned_foot = upper_position + upper_turn * (foot_position - upper_position);
+            let lower_turn = Quat::from_rotation_arc(
+                (turned_foot - knee).normalize(),
+                (reached - knee).normalize(),
+            );
+
+            let upper_rotation = upper_turn * upper.rotation();
+            let lower_rotation = lower_turn * upper_turn * lower.rotation();
+            let align = Quat::IDENTITY.slerp(Quat::from_rotation_arc(Vec3::Y, normal), rig.weight);
+            let foot_rotation = align * lower_turn * upper_turn * foot.rotation();
+
+            if let Ok((mut bone, _)) = transforms.get_mut(leg.upper) {
+                bone.rotation = upper_parent.rotation().inverse() * upper_rotation;
+            }
+            if let Ok((mut bone, _)) = transforms.get_mut(leg.lower) {
+                bone.rotation = upper_rotation.inverse() * lower_rotation;
+            }
+            if let Ok((mut bone, _)) = transforms.get_mut(leg.foot) {
+                bone.rotation = lower_rotation.inverse() * foot_rotation;
+            }
+        }
+    }
+}
@@ -1,7 +1,6 @@
 ///! character controller system
 ///! forked from the tnua shooter_like demo
 use animating::{AnimationState, animate_character, animation_patcher_system};
-use avian3d::math::Vector;
 use avian3d::prelude::*;
 use bevy::prelude::*;
 use bevy::{color::palettes::css, ecs::system::Query, gizmos::gizmos::Gizmos};
@@ -13,7 +12,7 @@ use bevy_tnua::builtins::{
 use bevy_tnua::control_helpers::{
     TnuaActionSlots, TnuaAirActionDefinition, TnuaAirActionsPlugin, TnuaHasTargetEntity,
 };
-use bevy_tnua::math::AsF32;
+use bevy_tnua::math::{AdjustPrecision, AsF32};
 use bevy_tnua::{TnuaConfig, TnuaGhostOverwrites, TnuaScheme};
 
 use bevy_tnua::{
@@ -27,21 +26,67 @@ use bevy_tnua::{builtins::TnuaBuiltinCrouch, math::float_consts, prelude::TnuaBu
 use bevy_tnua_avian3d::*;
 
 mod animating;
+mod animation_table;
 mod assets;
 pub mod config;
+mod description;
+mod firing;
+mod foot_ik;
+mod ragdoll;
+mod root_motion;
 mod sound;
+mod upper_body;
 mod weapon;
 
-use crate::character::animating::GltfSceneHandler;
-use crate::character::config::CharacterMotionConfig;
-use crate::character::weapon::equip_weapon;
+use crate::character::config::{CharacterMotionConfig, FallingThroughControlScheme, PlayerConfig};
+use crate::character::weapon::{equip_weapon, holster_weapon};
+use crate::gp::{StatKind, Stats};
 
-pub use weapon::{EquipWeapon, WeaponKind};
+pub use firing::{
+    EquippedWeapon, FireWeapon, Hit, Projectile, ReloadWeapon, WeaponFired, WeaponFiringPlugin,
+};
+pub use ragdoll::{DisableRagdoll, EnableRagdoll, RagdollBody};
+pub use root_motion::RootMotion;
+pub use sound::Surface;
+pub use weapon::{
+    EquipWeapon, HolsterWeapon, Weapon, WeaponDefinition, WeaponDelivery, WeaponInventory,
+    WeaponStats,
+};
 
 /// Marks an entity as the player character
 #[derive(Component, Debug)]
 pub struct WaltzPlayer;
 
+/// The direction the character faces, its model looks along the back of its transform.
+pub fn character_facing(transform: &Transform) -> Dir3 {
+    transform.back()
+}
+
+/// The forward Tnua turns the transform to, so the character faces `facing`.
+pub fn tnua_forward(facing: Dir3) -> Dir3 {
+    -facing
+}
+
+/// Moves the character to `position` facing `rotation`, at rest. The Tnua controller is replaced
+/// by a new one, so no action, ground or platform carries over from before the teleport.
+#[derive(Debug, Clone, Copy, PartialEq, EntityEvent)]
+pub struct TeleportCharacter {
+    pub entity: Entity,
+    pub position: Vec3,
+    pub rotation: Quat,
+}
+
+/// The character stands on the ground again, `speed` is how fast it was falling.
+#[derive(Debug, Clone, Copy, PartialEq, EntityEvent)]
+pub struct CharacterLanded {
+    pub entity: Entity,
+    pub speed: f32,
+}
+
+/// The falling speed of the last airborne frame, the landing already stops the body.
+#[derive(Component, Debug, Default)]
+struct FallingSpeed(Option<f32>);
+
 pub fn character_control_radar_visualization_system(
     query: Query<&TnuaObstacleRadar>,
     spatial_ext: TnuaSpatialExtAvian3d,
@@ -86,6 +131,8 @@ impl TnuaAirActionDefinition for WaltzTnuaCtrlScheme {
 pub struct WaltzAirActionSlots {
     #[slots(Jump)]
     jump: usize,
+    #[slots(Dash)]
+    dash: usize,
 }
 
 // impl TnuaAirActionDefinition for WaltzTnuaCtrlScheme {
@@ -105,6 +152,8 @@ pub struct WaltzAirActionSlots {
 impl TnuaHasTargetEntity for WaltzTnuaCtrlScheme {
     fn target_entity(action_state: &Self::ActionState) -> Option<Entity> {
         match action_state {
+            WaltzTnuaCtrlSchemeActionState::WallSlide(_, entity)
+            | WaltzTnuaCtrlSchemeActionState::Climb(_, entity, _) => Some(*entity),
             _ => None,
         }
     }
@@ -170,24 +219,35 @@ impl Plugin for WaltzCharacterPlugin {
             FixedUpdate,
         ));
 
+        app.register_type::<PlayerConfig>();
+        app.init_resource::<PlayerConfig>();
+
         app.add_plugins(assets::plugin);
+        app.add_plugins(animation_table::plugin);
+        app.add_plugins(description::plugin);
+        app.add_plugins(root_motion::plugin);
+        app.add_plugins(foot_ik::plugin);
+        app.add_plugins(ragdoll::plugin);
         app.add_plugins(sound::plugin);
+        app.add_plugins(upper_body::plugin);
         app.add_plugins(weapon::plugin);
 
-        // app.add_systems(Startup, setup_player);
-        app.add_systems(Startup, setup_demo_player);
-
         app.add_systems(Update, debug_character_position);
 
         app.add_systems(Update, character_control_radar_visualization_system);
         app.add_systems(Update, animation_patcher_system);
         app.add_systems(Update, animate_character);
+
+        app.add_systems(FixedUpdate, detect_landing);
+
+        app.add_observer(teleport_character);
     }
 }
 
 fn setup_character_with_entity_cmd(
     mut cmd: EntityCommands,
-    mut ctrl_scheme_cfg_assets: ResMut<Assets<WaltzTnuaCtrlSchemeConfig>>,
+    float_height: f32,
+    ctrl_scheme_cfg_assets: &mut Assets<WaltzTnuaCtrlSchemeConfig>,
 ) {
     cmd.insert((
         WaltzPlayer,
@@ -195,6 +255,16 @@ fn setup_character_with_entity_cmd(
         RigidBody::Dynamic,
     ));
 
+    let mut ctrl_scheme_cfg = WaltzTnuaCtrlSchemeConfig::default();
+    ctrl_scheme_cfg.basis.float_height = float_height;
+    let stats_base = [
+        (StatKind::JumpHeight, ctrl_scheme_cfg.jump.height),
+        (
+            StatKind::DashDistance,
+            ctrl_scheme_cfg.dash.horizontal_distance,
+        ),
+    ];
+
     cmd.insert((
         // `TnuaController` is Tnua's main interface with the user code. Read
         // examples/src/character_control_systems/platformer_control_systems.rs to see how
@@ -202,9 +272,7 @@ fn setup_character_with_entity_cmd(
         TnuaController::<WaltzTnuaCtrlScheme>::default(),
         // `TnuaConfig` holds the configuration for the Tnua controller. It can be loaded from a
         // file as an asset.
-        TnuaConfig::<WaltzTnuaCtrlScheme>(
-            ctrl_scheme_cfg_assets.add(WaltzTnuaCtrlSchemeConfig::default()),
-        ),
+        TnuaConfig::<WaltzTnuaCtrlScheme>(ctrl_scheme_cfg_assets.add(ctrl_scheme_cfg)),
     ));
 
     // The obstacle radar is used to detect obstacles around the player that the player can use
@@ -215,14 +283,23 @@ fn setup_character_with_entity_cmd(
     // physics simulation.
     cmd.insert(TnuaObstacleRadar::new(1.0, 3.0));
 
-    cmd.insert(CharacterMotionConfig {
+    let motion_config = CharacterMotionConfig {
         // speed with direction correction factor
         speed: 5.0 * 3.0,
         actions_in_air: 1,
         dash_distance: 10.0,
         one_way_platforms_min_proximity: 1.0,
         climb_speed: 10.0,
-    });
+        crouch_speed_factor: 0.2,
+    };
+
+    // the gear modifies these values, see `gp::stats`
+    cmd.insert(Stats::new(stats_base.into_iter().chain([
+        (StatKind::MoveSpeed, motion_config.speed),
+        (StatKind::ClimbSpeed, motion_config.climb_speed),
+        (StatKind::Armor, 0.0),
+    ])));
+    cmd.insert(motion_config);
 
     // use TnuaBlipReuseAvoidance to avoid initiating actions
     cmd.insert(TnuaBlipReuseAvoidance::<WaltzTnuaCtrlScheme>::default());
@@ -265,6 +342,9 @@ fn setup_character_with_entity_cmd(
     cmd.insert(TnuaToggle::default());
 
     cmd.insert(TnuaAnimatingState::<AnimationState>::default());
+    // measured from the clips, it moves the character while the state plays with root motion
+    cmd.insert(RootMotion::default());
+    cmd.insert(FallingSpeed::default());
 
     // The ghost sensor is used for detecting ghost platforms - platforms configured in the physics
     // backend to not contact with the character (or detect the contact but not apply physical
@@ -275,41 +355,75 @@ fn setup_character_with_entity_cmd(
     // This helper is used to operate the ghost sensor and ghost platforms and implement
     // fall-through behavior where the player can intentionally fall through a one-way platform.
     cmd.insert(TnuaSimpleFallThroughPlatformsHelper::default());
+    cmd.insert(FallingThroughControlScheme::default());
 
-    // handle the equip weapon action
+    // handle the equip and holster weapon actions
     cmd.observe(equip_weapon);
+    cmd.observe(holster_weapon);
 }
 
-fn setup_demo_player(
+fn detect_landing(
     mut commands: Commands,
-    mut meshes: ResMut<Assets<Mesh>>,
-    mut materials: ResMut<Assets<StandardMaterial>>,
-    ctrl_scheme_cfg_assets: ResMut<Assets<WaltzTnuaCtrlSchemeConfig>>,
+    mut characters: Query<(
+        Entity,
+        &TnuaController<WaltzTnuaCtrlScheme>,
+        &LinearVelocity,
+        &mut FallingSpeed,
+    )>,
 ) {
-    let cmd = commands.spawn((
-        Mesh3d(meshes.add(Capsule3d::new(0.5, 1.0))),
-        MeshMaterial3d(materials.add(Color::srgb(0.8, 0.7, 0.6))),
-        Collider::capsule(0.5, 1.0),
-        TnuaAvian3dSensorShape(Collider::cylinder(0.49, 0.0)),
-    ));
-
-    setup_character_with_entity_cmd(cmd, ctrl_scheme_cfg_assets);
+    for (entity, controller, velocity, mut falling_speed) in &mut characters {
+        if controller.basis_memory.standing_on_entity().is_none() {
+            falling_speed.0 = Some((-velocity.y as f32).max(0.0));
+        } else if let Some(speed) = falling_speed.0.take() {
+            commands.trigger(CharacterLanded { entity, speed });
+        }
+    }
 }
 
-fn setup_player(
+fn teleport_character(
+    teleport: On<TeleportCharacter>,
     mut commands: Commands,
-    asset_server: Res<AssetServer>,
-    ctrl_scheme_cfg_assets: ResMut<Assets<WaltzTnuaCtrlSchemeConfig>>,
+    mut characters: Query<(
+        &mut Transform,
+        Option<&mut Position>,
+        Option<&mut Rotation>,
+        Option<&mut LinearVelocity>,
+        Option<&mut AngularVelocity>,
+    )>,
 ) {
-    let cmd = commands.spawn((
-        WorldAssetRoot(asset_server.load("waltz/player.glb#Scene0")),
-        GltfSceneHandler {
-            names_from: asset_server.load("waltz/player.glb"),
-        },
-        Collider::capsule_endpoints(0.5, 0.5 * Vector::Y, 1.2 * Vector::Y),
-    ));
+    let TeleportCharacter {
+        entity,
+        position,
+        rotation,
+    } = *teleport.event();
+    let Ok((mut transform, physics_position, physics_rotation, linear_velocity, angular_velocity)) =
+        characters.get_mut(entity)
+    else {
+        return;
+    };
+
+    transform.translation = position;
+    transform.rotation = rotation;
+    // the physics would move the body back to where it was before the transform is synced
+    if let Some(mut physics_position) = physics_position {
+        physics_position.0 = position.adjust_precision();
+    }
+    if let Some(mut physics_rotation) = physics_rotation {
+        *physics_rotation = Rotation::from(rotation.adjust_precision());
+    }
+    if let Some(mut velocity) = linear_velocity {
+        velocity.0 = Default::default();
+    }
+    if let Some(mut velocity) = angular_velocity {
+        velocity.0 = Default::default();
+    }
 
-    setup_character_with_entity_cmd(cmd, ctrl_scheme_cfg_assets);
+    commands.entity(entity).insert((
+        TnuaController::<WaltzTnuaCtrlScheme>::default(),
+        TnuaGhostOverwrites::<WaltzTnuaCtrlScheme>::default(),
+        TnuaBlipReuseAvoidance::<WaltzTnuaCtrlScheme>::default(),
+        TnuaSimpleFallThroughPlatformsHelper::default(),
+    ));
 }
 
 pub fn debug_character_position(transform: Single<&Transform, With<WaltzPlayer>>) {
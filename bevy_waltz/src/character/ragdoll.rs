@@ -0,0 +1,500 @@
+//! The ragdoll takes over the skeleton of a character when it dies or is knocked back hard.
+//!
+//! Every bone of the [`RagdollConfig`] gets a capsule body reaching to its first child joint, tied
+//! to the body of its closest simulated ancestor by a spherical or a revolute joint. While the
+//! ragdoll is simulated the Tnua controller and the capsule body of the character are disabled, and
+//! the bones follow the bodies. Getting up moves the character to the ragdoll and blends the bones
+//! back to the animation.
+use avian3d::prelude::*;
+use bevy::{
+    animation::{AnimationSystems, AnimationTargetId},
+    prelude::*,
+    transform::TransformSystems,
+};
+use bevy_tnua::{TnuaToggle, prelude::TnuaController};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    camera::config::CollisionLayer,
+    character::{
+        WaltzTnuaCtrlScheme, WaltzTnuaCtrlSchemeActionDiscriminant,
+        foot_ik::{self, global_transform},
+    },
+    gp::{Died, Respawn},
+};
+
+pub(super) fn plugin(app: &mut App) {
+    app.register_type::<RagdollBody>()
+        .add_observer(enable_ragdoll)
+        .add_observer(disable_ragdoll)
+        .add_observer(ragdoll_on_death)
+        .add_observer(get_up_on_respawn)
+        .add_systems(Update, (ragdoll_on_knockback, tick_ragdoll_recovery))
+        .add_systems(
+            PostUpdate,
+            drive_ragdoll_bones
+                .after(AnimationSystems)
+                .after(foot_ik::solve_foot_ik)
+                .before(TransformSystems::Propagate),
+        );
+}
+
+fn default_mass() -> f32 {
+    5.0
+}
+
+fn default_knockback_speed() -> f32 {
+    15.0
+}
+
+fn default_knockback_recovery() -> f32 {
+    2.0
+}
+
+fn default_get_up_time() -> f32 {
+    0.5
+}
+
+/// The joint of a bone to the bone of its closest simulated ancestor, the limits are in degrees.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum RagdollJoint {
+    Spherical {
+        swing: f32,
+        twist: f32,
+    },
+    /// a hinge around `axis`, in the space of the bone
+    Revolute {
+        axis: [f32; 3],
+        min: f32,
+        max: f32,
+    },
+}
+
+impl Default for RagdollJoint {
+    fn default() -> Self {
+        RagdollJoint::Spherical {
+            swing: 45.0,
+            twist: 20.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RagdollBone {
+    pub bone: String,
+    /// radius of the capsule
+    pub radius: f32,
+    #[serde(default = "default_mass")]
+    pub mass: f32,
+    /// ignored on the root of the ragdoll
+    #[serde(default)]
+    pub joint: RagdollJoint,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RagdollConfig {
+    pub bones: Vec<RagdollBone>,
+    /// speed of a knockback that throws the character into the ragdoll
+    #[serde(default = "default_knockback_speed")]
+    pub knockback_speed: f32,
+    /// seconds a knocked back character stays a ragdoll before it gets up
+    #[serde(default = "default_knockback_recovery")]
+    pub knockback_recovery: f32,
+    /// seconds of the blend from the ragdoll pose back to the animation
+    #[serde(default = "default_get_up_time")]
+    pub get_up_time: f32,
+}
+
+impl RagdollConfig {
+    /// Finds the bones below the animation player, the ragdoll is left out if one is missing.
+    pub fn rig(
+        &self,
+        player: Entity,
+        children: &Query<&Children>,
+        parents: &Query<&ChildOf>,
+        bones: &Query<(&Name, &Transform)>,
+    ) -> Option<RagdollRig> {
+        let mut found = Vec::with_capacity(self.bones.len());
+        for bone in &self.bones {
+            let Some(entity) = children.iter_descendants(player).find(|entity| {
+                bones
+                    .get(*entity)
+                    .is_ok_and(|(name, _)| name.as_str() == bone.bone)
+            }) else {
+                error!("ragdoll is disabled, bone {} does not exist", bone.bone);
+                return None;
+            };
+            found.push((entity, bone.clone()));
+        }
+
+        // the ancestors come before their descendants, so the joints always find their parent body
+        found.sort_by_cached_key(|(entity, _)| parents.iter_ancestors(*entity).count());
+        let entities = found.iter().map(|(entity, _)| *entity).collect::<Vec<_>>();
+        let bones = found
+            .into_iter()
+            .map(|(entity, config)| RigBone {
+                entity,
+                parent: parents
+                    .iter_ancestors(entity)
+                    .find_map(|ancestor| entities.iter().position(|entity| *entity == ancestor)),
+                config,
+            })
+            .collect::<Vec<_>>();
+
+        if bones.iter().filter(|bone| bone.parent.is_none()).count() > 1 {
+            warn!(
+                "the ragdoll has more than one root bone, only the first one places the character"
+            );
+        }
+
+        Some(RagdollRig {
+            bones,
+            config: self.clone(),
+            state: RagdollState::Animated,
+        })
+    }
+}
+
+#[derive(Debug)]
+struct RigBone {
+    entity: Entity,
+    /// index of the closest simulated ancestor
+    parent: Option<usize>,
+    config: RagdollBone,
+}
+
+#[derive(Debug)]
+enum RagdollState {
+    Animated,
+    Simulated {
+        /// the body of every bone, in the order of the bones
+        bodies: Vec<Entity>,
+        joints: Vec<Entity>,
+        /// the global scale of every bone, the bodies are not scaled
+        scales: Vec<Vec3>,
+        /// height of the root bone above the character
+        root_height: f32,
+        /// seconds until the ragdoll gets up, `None` until [`DisableRagdoll`]
+        remaining: Option<f32>,
+    },
+    GettingUp {
+        elapsed: f32,
+        /// the local transforms of the bones when the simulation stopped
+        pose: Vec<Transform>,
+    },
+}
+
+/// The ragdoll bones of a character with the state of its ragdoll.
+#[derive(Component, Debug)]
+pub struct RagdollRig {
+    bones: Vec<RigBone>,
+    config: RagdollConfig,
+    state: RagdollState,
+}
+
+impl RagdollRig {
+    pub fn is_simulated(&self) -> bool {
+        matches!(self.state, RagdollState::Simulated { .. })
+    }
+}
+
+/// A simulated body of the ragdoll of `owner`.
+#[derive(Component, Debug, Clone, Copy, Reflect)]
+#[reflect(Component)]
+pub struct RagdollBody {
+    pub owner: Entity,
+}
+
+/// Switches the character to its ragdoll, it gets up after `duration` seconds, or on
+/// [`DisableRagdoll`] without one.
+#[derive(Debug, Clone, Copy, PartialEq, EntityEvent)]
+pub struct EnableRagdoll {
+    pub entity: Entity,
+    pub duration: Option<f32>,
+}
+
+/// Gets the character up from its ragdoll.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, EntityEvent)]
+pub struct DisableRagdoll {
+    pub entity: Entity,
+}
+
+fn ragdoll_on_death(died: On<Died>, mut commands: Commands, rigs: Query<(), With<RagdollRig>>) {
+    if rigs.contains(died.entity) {
+        commands.trigger(EnableRagdoll {
+            entity: died.entity,
+            duration: None,
+        });
+    }
+}
+
+fn get_up_on_respawn(respawn: On<Respawn>, mut commands: Commands, rigs: Query<&RagdollRig>) {
+    if rigs.get(respawn.entity).is_ok_and(RagdollRig::is_simulated) {
+        commands.trigger(DisableRagdoll {
+            entity: respawn.entity,
+        });
+    }
+}
+
+fn ragdoll_on_knockback(
+    mut commands: Commands,
+    characters: Query<(
+        Entity,
+        &RagdollRig,
+        &TnuaController<WaltzTnuaCtrlScheme>,
+        &LinearVelocity,
+    )>,
+) {
+    for (entity, rig, controller, velocity) in &characters {
+        if !matches!(rig.state, RagdollState::Animated)
+            || controller.action_discriminant()
+                != Some(WaltzTnuaCtrlSchemeActionDiscriminant::Knockback)
+        {
+            continue;
+        }
+
+        if rig.config.knockback_speed < velocity.length() as f32 {
+            debug!("{entity} is knocked into its ragdoll");
+            commands.trigger(EnableRagdoll {
+                entity,
+                duration: Some(rig.config.knockback_recovery),
+            });
+        }
+    }
+}
+
+fn tick_ragdoll_recovery(
+    time: Res<Time>,
+    mut commands: Commands,
+    mut rigs: Query<(Entity, &mut RagdollRig)>,
+) {
+    for (entity, mut rig) in &mut rigs {
+        if let RagdollState::Simulated {
+            remaining: Some(remaining),
+            ..
+        } = &mut rig.state
+        {
+            *remaining -= time.delta_secs();
+            if *remaining <= 0.0 {
+                commands.trigger(DisableRagdoll { entity });
+            }
+        }
+    }
+}
+
+fn enable_ragdoll(
+    enable: On<EnableRagdoll>,
+    mut commands: Commands,
+    mut characters: Query<(&mut RagdollRig, &Transform, &LinearVelocity)>,
+    bones: Query<(&GlobalTransform, Option<&Children>)>,
+    joints: Query<(), With<AnimationTargetId>>,
+) {
+    let EnableRagdoll { entity, duration } = *enable.event();
+    let Ok((mut rig, transform, velocity)) = characters.get_mut(entity) else {
+        return;
+    };
+    if rig.is_simulated() {
+        return;
+    }
+    if let Some(bone) = rig.bones.iter().find(|bone| !bones.contains(bone.entity)) {
+        warn!("ragdoll bone {} lost its transform", bone.config.bone);
+        return;
+    }
+
+    let mut bodies = Vec::with_capacity(rig.bones.len());
+    let mut poses = Vec::with_capacity(rig.bones.len());
+    let mut ragdoll_joints = Vec::new();
+    for bone in &rig.bones {
+        let Ok((global, children)) = bones.get(bone.entity) else {
+            return;
+        };
+        let (scale, rotation, translation) = global.to_scale_rotation_translation();
+
+        // the capsule reaches to the first child joint, a bone without one is a sphere
+        let end = children
+            .and_then(|children| children.iter().find(|child| joints.contains(*child)))
+            .and_then(|child| bones.get(child).ok())
+            .map(|(child, _)| rotation.inverse() * (child.translation() - translation));
+        let radius = bone.config.radius;
+        let collider = match end {
+            Some(end) if end.length() > 1e-3 => {
+                Collider::capsule_endpoints(radius, Vec3::ZERO, end)
+            }
+            _ => Collider::sphere(radius),
+        };
+
+        let body = commands
+            .spawn((
+                Name::new(format!("ragdoll {}", bone.config.bone)),
+                RagdollBody { owner: entity },
+                RigidBody::Dynamic,
+                collider,
+                Mass(bone.config.mass),
+                Transform::from_translation(translation).with_rotation(rotation),
+                LinearVelocity(velocity.0),
+                // the bodies only collide with the level, not with each other
+                CollisionLayers::new(CollisionLayer::Character, CollisionLayer::Terrain),
+            ))
+            .id();
+
+        if let Some(parent) = bone.parent {
+            let (parent_translation, parent_rotation, _) = poses[parent];
+            let parent_body = bodies[parent];
+            let anchor = parent_rotation.inverse() * (translation - parent_translation);
+            let joint = match &bone.config.joint {
+                RagdollJoint::Spherical { swing, twist } => commands
+                    .spawn(
+                        SphericalJoint::new(parent_body, body)
+                            .with_local_anchor1(anchor)
+                            .with_local_anchor2(Vec3::ZERO)
+                            .with_swing_limits(-swing.to_radians(), swing.to_radians())
+                            .with_twist_limits(-twist.to_radians(), twist.to_radians()),
+                    )
+                    .id(),
+                RagdollJoint::Revolute { axis, min, max } => commands
+                    .spawn(
+                        RevoluteJoint::new(parent_body, body)
+                            .with_local_anchor1(anchor)
+                            .with_local_anchor2(Vec3::ZERO)
+                            .with_hinge_axis(Vec3::from_array(*axis))
+                            .with_angle_limits(min.to_radians(), max.to_radians()),
+                    )
+                    .id(),
+            };
+            ragdoll_joints.push(joint);
+        }
+
+        bodies.push(body);
+        poses.push((translation, rotation, scale));
+    }
+
+    let root_height = poses.first().map_or(0.0, |(translation, ..)| {
+        translation.y - transform.translation.y
+    });
+    rig.state = RagdollState::Simulated {
+        bodies,
+        joints: ragdoll_joints,
+        scales: poses.into_iter().map(|(.., scale)| scale).collect(),
+        root_height,
+        remaining: duration,
+    };
+
+    info!("{entity} switches to its ragdoll");
+    commands
+        .entity(entity)
+        .insert((TnuaToggle::Disabled, RigidBodyDisabled, ColliderDisabled));
+}
+
+fn disable_ragdoll(
+    disable: On<DisableRagdoll>,
+    mut commands: Commands,
+    mut characters: Query<
+        (&mut RagdollRig, &mut Transform, &mut LinearVelocity),
+        Without<RagdollBody>,
+    >,
+    bones: Query<&Transform, (Without<RagdollRig>, Without<RagdollBody>)>,
+    bodies: Query<&Transform, With<RagdollBody>>,
+) {
+    let entity = disable.entity;
+    let Ok((mut rig, mut transform, mut velocity)) = characters.get_mut(entity) else {
+        return;
+    };
+    let RagdollState::Simulated {
+        bodies: ragdoll_bodies,
+        joints,
+        root_height,
+        ..
+    } = &rig.state
+    else {
+        return;
+    };
+
+    // the character stands up where the ragdoll lies
+    if let Some(root) = ragdoll_bodies
+        .first()
+        .and_then(|body| bodies.get(*body).ok())
+    {
+        transform.translation = root.translation - Vec3::Y * *root_height;
+    }
+    velocity.0 = Vec3::ZERO;
+
+    for ragdoll_entity in joints.iter().chain(ragdoll_bodies) {
+        commands.entity(*ragdoll_entity).despawn();
+    }
+
+    let pose = rig
+        .bones
+        .iter()
+        .map(|bone| bones.get(bone.entity).copied().unwrap_or_default())
+        .collect();
+    rig.state = RagdollState::GettingUp { elapsed: 0.0, pose };
+
+    info!("{entity} gets up from its ragdoll");
+    commands
+        .entity(entity)
+        .insert(TnuaToggle::Enabled)
+        .remove::<(RigidBodyDisabled, ColliderDisabled)>();
+}
+
+/// Poses the bones after the bodies while simulated, and blends them from the ragdoll pose to the
+/// animation while getting up.
+fn drive_ragdoll_bones(
+    mut rigs: Query<&mut RagdollRig>,
+    bodies: Query<&Transform, With<RagdollBody>>,
+    mut transforms: Query<(&mut Transform, Option<&ChildOf>), Without<RagdollBody>>,
+    time: Res<Time>,
+) {
+    for mut rig in &mut rigs {
+        let rig = &mut *rig;
+        match &mut rig.state {
+            RagdollState::Animated => {}
+            RagdollState::Simulated {
+                bodies: ragdoll_bodies,
+                scales,
+                ..
+            } => {
+                // the parents are posed first, the children are placed relative to them
+                for ((bone, body), scale) in rig.bones.iter().zip(ragdoll_bodies).zip(scales) {
+                    let Ok(body) = bodies.get(*body) else {
+                        continue;
+                    };
+                    let Some(parent) = transforms
+                        .get(bone.entity)
+                        .ok()
+                        .and_then(|(_, child_of)| child_of)
+                        .and_then(|child_of| global_transform(child_of.parent(), &transforms))
+                    else {
+                        continue;
+                    };
+
+                    let global = GlobalTransform::from(body.with_scale(*scale));
+                    if let Ok((mut transform, _)) = transforms.get_mut(bone.entity) {
+                        *transform = global.reparented_to(&parent);
+                    }
+                }
+            }
+            RagdollState::GettingUp { elapsed, pose } => {
+                *elapsed += time.delta_secs();
+                let blend = (*elapsed / rig.config.get_up_time.max(1e-3)).min(1.0);
+
+                for (bone, pose) in rig.bones.iter().zip(pose.iter()) {
+                    let Ok((mut transform, _)) = transforms.get_mut(bone.entity) else {
+                        continue;
+                    };
+
+                    transform.rotation = pose.rotation.slerp(transform.rotation, blend);
+                    // the root keeps its animated position, the character moved to the ragdoll
+                    if bone.parent.is_some() {
+                        transform.translation = pose.translation.lerp(transform.translation, blend);
+                    }
+                }
+
+                if blend >= 1.0 {
+                    rig.state = RagdollState::Animated;
+                }
+            }
+        }
+    }
+}
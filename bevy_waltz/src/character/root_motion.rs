@@ -0,0 +1,173 @@
+//! Root motion moves the character by the motion its clips give to the root bone, instead of the
+//! physics, so the feet stop sliding.
+//!
+//! The clips keep animating the root bone, every frame after the animations are applied its motion
+//! since the previous frame is measured into [`RootMotion`] and the bone is put back to its rest
+//! pose, so the clip plays in place. The character control feeds the motion to Tnua while the
+//! state plays a clip with `root_motion` in the animation table, otherwise the motion is only
+//! measured. The motion drives the walk basis and the climbing speed, and it replaces the
+//! horizontal velocity the dash and the knockback give to the body.
+use avian3d::prelude::{LinearVelocity, PhysicsSystems};
+use bevy::{animation::AnimationSystems, prelude::*, transform::TransformSystems};
+use bevy_tnua::prelude::TnuaController;
+
+use crate::character::{
+    WaltzTnuaCtrlScheme, WaltzTnuaCtrlSchemeActionDiscriminant, animating::AnimationsHandler,
+};
+
+pub(super) fn plugin(app: &mut App) {
+    app.register_type::<RootMotion>()
+        .add_systems(
+            PostUpdate,
+            extract_root_motion
+                .after(AnimationSystems)
+                .before(TransformSystems::Propagate),
+        )
+        .add_systems(
+            FixedPostUpdate,
+            drive_actions.before(PhysicsSystems::StepSimulation),
+        );
+}
+
+/// The motion of the root bone during the last frame, in world space.
+#[derive(Component, Debug, Clone, Default, Reflect)]
+#[reflect(Component)]
+pub struct RootMotion {
+    /// the playing state moves the character by its root motion
+    pub active: bool,
+    pub velocity: Vec3,
+    /// turn around the up axis, in radians
+    pub yaw: f32,
+}
+
+/// The root bone of a character skeleton with its rest pose.
+#[derive(Debug)]
+pub struct RootBone {
+    entity: Entity,
+    rest: Transform,
+    /// the animated pose of the previous frame, before it was put back to rest
+    previous: Option<Transform>,
+    /// completed loops of the current clips on the previous frame
+    completions: u32,
+}
+
+impl RootBone {
+    /// Finds the bone named `name` below the animation player, its current pose is the rest pose
+    /// since no animation played yet.
+    pub fn find(
+        name: &str,
+        player: Entity,
+        children: &Query<&Children>,
+        bones: &Query<(&Name, &Transform)>,
+    ) -> Option<Self> {
+        children
+            .iter_descendants(player)
+            .find_map(|entity| {
+                bones
+                    .get(entity)
+                    .ok()
+                    .filter(|(bone_name, _)| bone_name.as_str() == name)
+                    .map(|(_, transform)| (entity, *transform))
+            })
+            .map(|(entity, rest)| Self {
+                entity,
+                rest,
+                previous: None,
+                completions: 0,
+            })
+    }
+}
+
+pub(super) fn extract_root_motion(
+    mut characters: Query<(&mut AnimationsHandler, &mut RootMotion)>,
+    animation_players_query: Query<&AnimationPlayer>,
+    mut bones: Query<(&mut Transform, &ChildOf)>,
+    parents: Query<&GlobalTransform>,
+    time: Res<Time>,
+) {
+    let dt = time.delta_secs();
+
+    for (mut handler, mut root_motion) in &mut characters {
+        let active = handler.state.is_some_and(|state| {
+            handler
+                .clips
+                .get(&state)
+                .is_some_and(|clip| clip.root_motion)
+        });
+        let completions = animation_players_query
+            .get(handler.player_entity)
+            .map(|player| {
+                handler
+                    .current_nodes()
+                    .iter()
+                    .filter_map(|node| player.animation(*node))
+                    .map(|animation| animation.completions())
+                    .sum()
+            })
+            .unwrap_or_default();
+
+        let Some(root_bone) = handler.root_bone.as_mut() else {
+            continue;
+        };
+        let Ok((mut bone, child_of)) = bones.get_mut(root_bone.entity) else {
+            continue;
+        };
+        let parent = parents.get(child_of.parent()).copied().unwrap_or_default();
+
+        let pose = *bone;
+        // a clip that looped jumps back to its first pose, that frame has no motion
+        let looped = completions != root_bone.completions;
+        root_bone.completions = completions;
+
+        let (velocity, yaw) = match root_bone.previous.replace(pose) {
+            Some(previous) if !looped && dt > 0.0 => {
+                let delta = parent
+                    .affine()
+                    .transform_vector3(pose.translation - previous.translation);
+                let (_, parent_rotation, _) = parent.to_scale_rotation_translation();
+                let turn = (parent_rotation * pose.rotation)
+                    * (parent_rotation * previous.rotation).inverse();
+                (delta / dt, turn.to_euler(EulerRot::YXZ).0)
+            }
+            _ => (Vec3::ZERO, 0.0),
+        };
+
+        *root_motion = RootMotion {
+            active,
+            velocity,
+            yaw,
+        };
+
+        if active {
+            bone.translation = root_bone.rest.translation;
+            bone.rotation = root_bone.rest.rotation;
+        }
+    }
+}
+
+/// The dash and the knockback push the body by themselves, while their clip has root motion the
+/// horizontal velocity they reached is replaced by the motion of the clip, gravity still applies.
+fn drive_actions(
+    mut characters: Query<(
+        &RootMotion,
+        &TnuaController<WaltzTnuaCtrlScheme>,
+        &mut LinearVelocity,
+    )>,
+) {
+    for (root_motion, controller, mut velocity) in &mut characters {
+        if !root_motion.active {
+            continue;
+        }
+
+        if matches!(
+            controller.action_discriminant(),
+            Some(
+                WaltzTnuaCtrlSchemeActionDiscriminant::Dash
+                    | WaltzTnuaCtrlSchemeActionDiscriminant::Knockback
+            )
+        ) {
+            velocity.x = root_motion.velocity.x;
+            velocity.z = root_motion.velocity.z;
+        }
+    }
+}
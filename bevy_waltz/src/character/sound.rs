@@ -1,24 +1,114 @@
+//! Player sounds: the jump grunt, footsteps and landings.
+//!
+//! Every sound is a spatial emitter spawned as a child of the player, the camera carries the
+//! [`SpatialListener`]. Footsteps and landings use the sound set of the [`Surface`] the player
+//! stands on.
 use std::time::Duration;
 
-use bevy::prelude::*;
+use bevy::{audio::Volume, platform::collections::HashMap, prelude::*};
 use bevy_tnua::{builtins::TnuaBuiltinJumpMemory, prelude::TnuaController};
+use serde::Deserialize;
 
 use crate::character::{
-    WaltzPlayer, WaltzTnuaCtrlScheme, WaltzTnuaCtrlSchemeActionState, assets::CharacterAssets,
+    CharacterLanded, WaltzPlayer, WaltzTnuaCtrlScheme, WaltzTnuaCtrlSchemeActionState,
+    assets::CharacterAssets, config::PlayerConfig,
 };
 
+/// Height of the jump grunt above the feet of the player.
+const VOICE_HEIGHT: f32 = 1.6;
+
 pub(super) fn plugin(app: &mut App) {
-    app.add_systems(Update, (character_jump, character_movement, character_land));
+    app.register_type::<Surface>();
+    app.init_resource::<SurfaceSounds>();
+    app.add_observer(character_land);
+    app.add_systems(Update, (character_jump, character_movement));
+}
+
+/// The material of a ground entity, it picks the footstep and landing sounds.
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Reflect, Deserialize)]
+#[reflect(Component)]
+pub enum Surface {
+    #[default]
+    Stone,
+    Grass,
+    Metal,
+}
+
+#[derive(Debug, Clone)]
+struct SurfaceSoundSet {
+    footstep: Handle<AudioSource>,
+    landing: Handle<AudioSource>,
+    volume: f32,
+    /// playback speed, which also shifts the pitch
+    speed: f32,
+}
+
+#[derive(Resource, Debug)]
+struct SurfaceSounds {
+    sets: HashMap<Surface, SurfaceSoundSet>,
+}
+
+impl SurfaceSounds {
+    /// The sound set of the surface, ground without a set sounds like the default surface.
+    fn get(&self, surface: Surface) -> &SurfaceSoundSet {
+        self.sets
+            .get(&surface)
+            .or_else(|| self.sets.get(&Surface::default()))
+            .expect("the default surface has a sound set")
+    }
+}
+
+impl FromWorld for SurfaceSounds {
+    fn from_world(world: &mut World) -> Self {
+        let assets_server = world.resource::<AssetServer>();
+        // there is a single step recording for now, the surfaces only differ in pitch and volume
+        let walking: Handle<AudioSource> = assets_server.load("waltz/audio/walking.ogg");
+        let set = |volume, speed| SurfaceSoundSet {
+            footstep: walking.clone(),
+            landing: walking.clone(),
+            volume,
+            speed,
+        };
+
+        Self {
+            sets: HashMap::from_iter([
+                (Surface::Stone, set(1.0, 1.0)),
+                (Surface::Grass, set(0.6, 0.85)),
+                (Surface::Metal, set(1.0, 1.3)),
+            ]),
+        }
+    }
+}
+
+/// Plays the sound from a spatial emitter attached to the player.
+fn play_on_player(
+    commands: &mut Commands,
+    player: Entity,
+    sound: Handle<AudioSource>,
+    height: f32,
+    volume: f32,
+    speed: f32,
+) {
+    commands.spawn((
+        AudioPlayer(sound),
+        PlaybackSettings::DESPAWN
+            .with_spatial(true)
+            .with_volume(Volume::Linear(volume))
+            .with_speed(speed),
+        Transform::from_xyz(0.0, height, 0.0),
+        ChildOf(player),
+    ));
 }
 
 fn character_jump(
     mut commands: Commands,
-    character: Single<&TnuaController<WaltzTnuaCtrlScheme>, With<WaltzPlayer>>,
+    character: Single<(Entity, &TnuaController<WaltzTnuaCtrlScheme>), With<WaltzPlayer>>,
     character_assets: ResMut<CharacterAssets>,
     mut is_jumping: Local<bool>,
     mut sound_cooldown: Local<Option<Timer>>,
     time: Res<Time>,
 ) {
+    let (player, character) = character.into_inner();
     let sound_cooldown = sound_cooldown
         .get_or_insert_with(|| Timer::new(Duration::from_millis(1000), TimerMode::Once));
     sound_cooldown.tick(time.delta());
@@ -39,17 +129,102 @@ fn character_jump(
     *is_jumping = true;
 
     if sound_cooldown.is_finished() {
-        // todo: play sound
-        commands.spawn((
-            AudioPlayer(character_assets.jump_sound.clone()),
-            PlaybackSettings::ONCE,
-        ));
+        play_on_player(
+            &mut commands,
+            player,
+            character_assets.jump_sound.clone(),
+            VOICE_HEIGHT,
+            1.0,
+            1.0,
+        );
 
         sound_cooldown.reset();
         info!("play jump sound");
     }
 }
 
-fn character_movement() {}
+/// Plays a footstep every stride, so the steps follow the running speed.
+fn character_movement(
+    mut commands: Commands,
+    character: Single<(Entity, &TnuaController<WaltzTnuaCtrlScheme>), With<WaltzPlayer>>,
+    surfaces: Query<&Surface>,
+    surface_sounds: Res<SurfaceSounds>,
+    config: Res<PlayerConfig>,
+    time: Res<Time>,
+    mut stride: Local<f32>,
+) {
+    let (player, controller) = character.into_inner();
+    let sound_config = &config.sound;
 
-fn character_land() {}
+    let Some(ground) = controller.basis_memory.standing_on_entity() else {
+        // the first step after the landing comes after half a stride
+        *stride = 0.5 * sound_config.stride_length;
+        return;
+    };
+
+    let speed = controller.basis_memory.running_velocity.length();
+    if speed < sound_config.min_footstep_speed {
+        *stride = 0.5 * sound_config.stride_length;
+        return;
+    }
+
+    *stride += speed * time.delta_secs();
+    if *stride < sound_config.stride_length {
+        return;
+    }
+    *stride -= sound_config.stride_length;
+
+    let sprint = (speed / config.sprint_effect_speed_threshold).min(1.0);
+    let volume = sound_config.walk_volume.lerp(1.0, sprint);
+
+    let surface = surfaces.get(ground).copied().unwrap_or_default();
+    let set = surface_sounds.get(surface);
+    play_on_player(
+        &mut commands,
+        player,
+        set.footstep.clone(),
+        0.0,
+        volume * set.volume,
+        set.speed,
+    );
+}
+
+/// Plays a landing louder the faster the player hit the ground.
+fn character_land(
+    landed: On<CharacterLanded>,
+    mut commands: Commands,
+    players: Query<&TnuaController<WaltzTnuaCtrlScheme>, With<WaltzPlayer>>,
+    surfaces: Query<&Surface>,
+    surface_sounds: Res<SurfaceSounds>,
+    config: Res<PlayerConfig>,
+) {
+    let Ok(controller) = players.get(landed.entity) else {
+        return;
+    };
+    let (speed, sound_config) = (landed.speed, &config.sound);
+
+    if speed < sound_config.min_landing_speed {
+        return;
+    }
+
+    let impact = (speed - sound_config.min_landing_speed)
+        / (sound_config.max_landing_speed - sound_config.min_landing_speed).max(1e-3);
+    let volume = 0.2_f32.lerp(1.0, impact.min(1.0));
+
+    let surface = controller
+        .basis_memory
+        .standing_on_entity()
+        .and_then(|ground| surfaces.get(ground).ok())
+        .copied()
+        .unwrap_or_default();
+    let set = surface_sounds.get(surface);
+    debug!("play landing sound at {speed} on {surface:?}");
+    play_on_player(
+        &mut commands,
+        landed.entity,
+        set.landing.clone(),
+        0.0,
+        volume * set.volume,
+        set.speed,
+    );
+}
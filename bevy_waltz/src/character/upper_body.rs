@@ -0,0 +1,210 @@
+//! The upper body layer plays the aim and fire clips over the locomotion, so the character shoots
+//! while it moves.
+//!
+//! The upper body is every joint whose name starts with one of the `bone_prefixes` of the
+//! [`UpperBodyConfig`], together with the joints below it, the rest of the skeleton is the lower
+//! body. Both are mask groups of the animation graph: the locomotion node masks the upper body out
+//! while the layer is active, the layer node always masks the lower body out.
+use bevy::{
+    animation::{AnimationTargetId, RepeatAnimation, graph::AnimationMask},
+    platform::collections::HashMap,
+    prelude::*,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    camera::WaltzCamera,
+    character::{EquippedWeapon, WeaponFired, animating::AnimationsHandler},
+};
+
+const LOWER_BODY_GROUP: u32 = 0;
+const UPPER_BODY_GROUP: u32 = 1;
+
+const LOWER_BODY_MASK: AnimationMask = 1 << LOWER_BODY_GROUP;
+const UPPER_BODY_MASK: AnimationMask = 1 << UPPER_BODY_GROUP;
+
+pub(super) fn plugin(app: &mut App) {
+    app.add_observer(request_fire_clip)
+        .add_systems(Update, animate_upper_body);
+}
+
+fn default_fire_hold() -> f32 {
+    0.6
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct UpperBodyConfig {
+    /// joints whose name starts with one of these, ignoring the case, and the joints below them
+    /// make up the upper body
+    pub bone_prefixes: Vec<String>,
+    /// the clip looping while aiming
+    pub aim: String,
+    /// the clip played once per shot
+    pub fire: String,
+    /// seconds the layer stays up after a shot without aiming
+    #[serde(default = "default_fire_hold")]
+    pub fire_hold: f32,
+}
+
+impl UpperBodyConfig {
+    /// Adds the layer to the graph, next to the `locomotion` node, the layer is left out if the
+    /// glTF misses one of its clips, which is reported when the table loads.
+    pub fn resolve(
+        &self,
+        graph: &mut AnimationGraph,
+        named_clips: &HashMap<String, Handle<AnimationClip>>,
+        locomotion: AnimationNodeIndex,
+    ) -> Option<UpperBodyLayer> {
+        let (Some(aim), Some(fire)) = (named_clips.get(&self.aim), named_clips.get(&self.fire))
+        else {
+            return None;
+        };
+
+        // inactive until the character aims or fires
+        let layer = graph.add_blend_with_mask(LOWER_BODY_MASK | UPPER_BODY_MASK, 1.0, graph.root);
+        let aim = graph.add_clip(aim.clone(), 1.0, layer);
+        let fire = graph.add_clip(fire.clone(), 1.0, layer);
+
+        Some(UpperBodyLayer {
+            locomotion,
+            layer,
+            aim,
+            fire,
+            fire_hold: self.fire_hold,
+            since_fire: f32::INFINITY,
+            fire_requested: false,
+            active: false,
+        })
+    }
+
+    /// Puts every animated joint below `player` in the upper or the lower body mask group.
+    pub fn add_mask_groups(
+        &self,
+        graph: &mut AnimationGraph,
+        player: Entity,
+        children: &Query<&Children>,
+        joints: &Query<(Option<&Name>, Option<&AnimationTargetId>)>,
+    ) {
+        let prefixes = self
+            .bone_prefixes
+            .iter()
+            .map(|prefix| prefix.to_lowercase())
+            .collect::<Vec<_>>();
+
+        let mut upper_body_joints = 0;
+        let mut stack = vec![(player, false)];
+        while let Some((entity, parent_upper)) = stack.pop() {
+            let Ok((name, target)) = joints.get(entity) else {
+                continue;
+            };
+
+            let upper = parent_upper
+                || name.is_some_and(|name| {
+                    let name = name.as_str().to_lowercase();
+                    prefixes.iter().any(|prefix| name.starts_with(prefix))
+                });
+
+            if let Some(target) = target {
+                if upper {
+                    upper_body_joints += 1;
+                    graph.add_target_to_mask_group(*target, UPPER_BODY_GROUP);
+                } else {
+                    graph.add_target_to_mask_group(*target, LOWER_BODY_GROUP);
+                }
+            }
+
+            if let Ok(entity_children) = children.get(entity) {
+                stack.extend(entity_children.iter().map(|child| (child, upper)));
+            }
+        }
+
+        if upper_body_joints == 0 {
+            warn!(
+                "no joint matches the upper body prefixes {:?}",
+                self.bone_prefixes
+            );
+        }
+    }
+}
+
+/// The nodes of the upper body layer in the animation graph, with its state.
+#[derive(Debug)]
+pub struct UpperBodyLayer {
+    /// the parent of every locomotion clip
+    locomotion: AnimationNodeIndex,
+    layer: AnimationNodeIndex,
+    aim: AnimationNodeIndex,
+    fire: AnimationNodeIndex,
+    fire_hold: f32,
+    /// seconds since the last shot
+    since_fire: f32,
+    fire_requested: bool,
+    active: bool,
+}
+
+fn request_fire_clip(fired: On<WeaponFired>, mut handlers: Query<&mut AnimationsHandler>) {
+    if let Ok(mut handler) = handlers.get_mut(fired.event().entity) {
+        if let Some(layer) = handler.upper_body.as_mut() {
+            layer.fire_requested = true;
+        }
+    }
+}
+
+fn animate_upper_body(
+    mut handlers: Query<(&mut AnimationsHandler, Has<EquippedWeapon>)>,
+    mut animation_players_query: Query<(&mut AnimationPlayer, &AnimationGraphHandle)>,
+    mut animation_graphs_assets: ResMut<Assets<AnimationGraph>>,
+    camera: Option<Single<&WaltzCamera>>,
+    time: Res<Time>,
+) {
+    let aiming = camera.is_some_and(|camera| camera.is_aiming());
+
+    for (mut handler, armed) in &mut handlers {
+        let player_entity = handler.player_entity;
+        let Some(layer) = handler.upper_body.as_mut() else {
+            continue;
+        };
+        let Ok((mut player, graph_handle)) = animation_players_query.get_mut(player_entity) else {
+            continue;
+        };
+
+        layer.since_fire += time.delta_secs();
+        if std::mem::take(&mut layer.fire_requested) {
+            layer.since_fire = 0.0;
+            player.start(layer.fire).set_repeat(RepeatAnimation::Never);
+        }
+
+        let active = armed && (aiming || layer.since_fire < layer.fire_hold);
+        if active != layer.active {
+            layer.active = active;
+            if let Some(mut graph) = animation_graphs_assets.get_mut(graph_handle) {
+                if let Some(node) = graph.get_mut(layer.locomotion) {
+                    node.mask = if active { UPPER_BODY_MASK } else { 0 };
+                }
+                if let Some(node) = graph.get_mut(layer.layer) {
+                    node.mask = if active {
+                        LOWER_BODY_MASK
+                    } else {
+                        LOWER_BODY_MASK | UPPER_BODY_MASK
+                    };
+                }
+            }
+
+            if active && !player.is_playing_animation(layer.aim) {
+                player.start(layer.aim).repeat();
+            }
+        }
+
+        // the fire clip replaces the aim pose until it is done
+        let firing = player
+            .animation(layer.fire)
+            .is_some_and(|animation| !animation.is_finished());
+        if let Some(animation) = player.animation_mut(layer.aim) {
+            animation.set_weight(if firing { 0.0 } else { 1.0 });
+        }
+        if let Some(animation) = player.animation_mut(layer.fire) {
+            animation.set_weight(if firing { 1.0 } else { 0.0 });
+        }
+    }
+}
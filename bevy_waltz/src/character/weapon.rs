@@ -1,58 +1,307 @@
-use bevy::prelude::*;
+//! Weapons are assets loaded from `*.weapon.toml` files, carried in the slots of a
+//! [`WeaponInventory`] and attached to the character skeleton while equipped. The weapons a
+//! character starts with are listed in the `[inventory]` of its `*.character.toml` file.
+use bevy::{
+    asset::{AssetLoader, LoadContext, io::Reader},
+    prelude::*,
+    world_serialization::WorldAsset,
+};
 use bone_attachments::{
-    BoneAttachmentsPlugin, relationship::AttachedTo, scene::SceneAttachmentExt,
+    BoneAttachmentsPlugin, relationship::Attachments, scene::SceneAttachmentExt,
 };
+use serde::{Deserialize, Serialize};
 
-const PISTOL_PATH: &str = "waltz/pistol_skeleton2.glb";
+use crate::{
+    character::firing::{EquippedWeapon, WeaponFiringPlugin},
+    config::{GameConfigError, read_toml},
+    gp::DamageKind,
+};
 
 pub(super) fn plugin(app: &mut App) {
-    app.add_plugins(BoneAttachmentsPlugin);
+    app.add_plugins((BoneAttachmentsPlugin, WeaponFiringPlugin))
+        .init_asset::<WeaponDefinition>()
+        .register_asset_loader(WeaponDefinitionLoader)
+        .register_type::<WeaponInventory>()
+        .register_type::<Weapon>();
+}
+
+/// A weapon, loaded from a `*.weapon.toml` file.
+#[derive(Asset, TypePath, Debug)]
+pub struct WeaponDefinition {
+    pub name: String,
+    /// the weapon scene, its skeleton is bound to the skeleton of the character
+    #[dependency]
+    pub scene: Handle<WorldAsset>,
+    /// the bone of the character holding the weapon, shots start from it
+    pub attach_bone: String,
+    pub stats: WeaponStats,
+}
+
+#[derive(Debug, Clone, PartialEq, Reflect, Serialize, Deserialize)]
+#[reflect(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct WeaponStats {
+    /// shots per second
+    pub fire_rate: f32,
+    /// keeps firing while the trigger is held
+    pub automatic: bool,
+    pub magazine_size: u32,
+    pub damage: f32,
+    pub damage_kind: DamageKind,
+    /// max angle in degrees between the aim direction and a shot
+    pub spread: f32,
+    /// seconds to refill the magazine
+    pub reload_time: f32,
+    /// camera trauma added by every shot of the player
+    pub recoil: f32,
+    pub delivery: WeaponDelivery,
+}
+
+/// How a shot reaches its target.
+#[derive(Debug, Clone, PartialEq, Reflect, Serialize, Deserialize)]
+#[reflect(Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum WeaponDelivery {
+    /// hits the first collider along a ray instantly
+    Hitscan { range: f32 },
+    /// spawns a physics body that hits the first collider it sweeps through
+    Projectile {
+        speed: f32,
+        radius: f32,
+        gravity_scale: f32,
+        /// seconds until a projectile that hit nothing is despawned
+        lifetime: f32,
+    },
+}
+
+/// The content of a `*.weapon.toml` file, `model` is the path of the glTF file.
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct WeaponFile {
+    name: String,
+    model: String,
+    attach_bone: String,
+    stats: WeaponStats,
+}
+
+#[derive(Default, TypePath)]
+struct WeaponDefinitionLoader;
+
+impl AssetLoader for WeaponDefinitionLoader {
+    type Asset = WeaponDefinition;
+    type Settings = ();
+    type Error = GameConfigError;
+
+    async fn load(
+        &self,
+        reader: &mut dyn Reader,
+        _settings: &(),
+        load_context: &mut LoadContext<'_>,
+    ) -> Result<Self::Asset, Self::Error> {
+        let file: WeaponFile = read_toml(reader).await?;
+
+        Ok(WeaponDefinition {
+            scene: load_context.load(GltfAssetLabel::Scene(0).from_asset(file.model)),
+            name: file.name,
+            attach_bone: file.attach_bone,
+            stats: file.stats,
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["weapon.toml"]
+    }
 }
 
-#[derive(Debug, Clone, Reflect, Copy, Eq, PartialEq)]
-pub enum WeaponKind {
-    Pistol,
+/// The weapons a character carries.
+#[derive(Component, Debug, Reflect)]
+#[reflect(Component)]
+pub struct WeaponInventory {
+    slots: Vec<Option<Handle<WeaponDefinition>>>,
+    /// rounds left in the magazine of each slot, `None` for a full magazine
+    rounds: Vec<Option<u32>>,
+    /// the slot of the equipped weapon, `None` while holstered
+    equipped: Option<usize>,
 }
 
+impl WeaponInventory {
+    pub fn new(slot_count: usize) -> Self {
+        Self {
+            slots: vec![None; slot_count],
+            rounds: vec![None; slot_count],
+            equipped: None,
+        }
+    }
+
+    pub fn slot_count(&self) -> usize {
+        self.slots.len()
+    }
+
+    pub fn slot(&self, slot: usize) -> Option<&Handle<WeaponDefinition>> {
+        self.slots.get(slot).and_then(Option::as_ref)
+    }
+
+    /// Puts a weapon into the slot and returns the one it replaces.
+    /// An equipped weapon stays attached until it is equipped or holstered again.
+    pub fn set_slot(
+        &mut self,
+        slot: usize,
+        weapon: Option<Handle<WeaponDefinition>>,
+    ) -> Option<Handle<WeaponDefinition>> {
+        let Some(current) = self.slots.get_mut(slot) else {
+            warn!("weapon inventory has no slot {slot}");
+            return weapon;
+        };
+
+        self.rounds[slot] = None;
+        std::mem::replace(current, weapon)
+    }
+
+    /// Rounds left in the magazine of the slot, `None` for a full magazine.
+    pub fn rounds(&self, slot: usize) -> Option<u32> {
+        self.rounds.get(slot).copied().flatten()
+    }
+
+    /// Remembers the rounds of the equipped weapon while it is holstered.
+    fn stash_rounds(&mut self, equipped: Option<&EquippedWeapon>) {
+        if let (Some(slot), Some(equipped)) = (self.equipped, equipped) {
+            self.rounds[slot] = Some(equipped.rounds);
+        }
+    }
+
+    pub fn equipped(&self) -> Option<usize> {
+        self.equipped
+    }
+
+    /// The first occupied slot after the equipped one, wrapping around.
+    pub fn next_slot(&self) -> Option<usize> {
+        let count = self.slots.len();
+        let start = self.equipped.map(|slot| slot + 1).unwrap_or_default();
+
+        (0..count)
+            .map(|offset| (start + offset) % count)
+            .find(|slot| Some(*slot) != self.equipped && self.slots[*slot].is_some())
+    }
+}
+
+/// The equipped weapon, lives on the attached weapon scene.
+#[derive(Component, Debug, Clone, Reflect)]
+#[reflect(Component)]
+pub struct Weapon {
+    pub definition: Handle<WeaponDefinition>,
+    /// the inventory slot the weapon was equipped from
+    pub slot: usize,
+}
+
+/// Equips the weapon of an inventory slot, selecting the equipped slot again holsters it.
 #[derive(Debug, Clone, Copy, Eq, PartialEq, EntityEvent, Reflect)]
 pub struct EquipWeapon {
     entity: Entity,
-    kind: WeaponKind,
+    slot: usize,
 }
 
-#[derive(Component, Debug)]
-pub struct Weapon {
-    kind: WeaponKind,
+impl EquipWeapon {
+    pub fn new(entity: Entity, slot: usize) -> Self {
+        Self { entity, slot }
+    }
 }
 
-impl EquipWeapon {
-    pub fn new(entity: Entity, kind: WeaponKind) -> Self {
-        Self { entity, kind }
+/// Holsters the equipped weapon and detaches its scene from the character.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, EntityEvent, Reflect)]
+pub struct HolsterWeapon {
+    entity: Entity,
+}
+
+impl HolsterWeapon {
+    pub fn new(entity: Entity) -> Self {
+        Self { entity }
+    }
+}
+
+/// Despawns the attached weapon scenes of the character, which also removes their `AttachedTo`.
+fn detach_weapons(
+    commands: &mut Commands,
+    character: Entity,
+    attachments: &Query<&Attachments>,
+    weapons: &Query<&Weapon>,
+) {
+    let Ok(attachments) = attachments.get(character) else {
+        return;
+    };
+
+    for attachment in attachments.iter() {
+        if weapons.contains(attachment) {
+            commands.entity(attachment).despawn();
+        }
     }
 }
 
 pub fn equip_weapon(
     equip_weapon: On<EquipWeapon>,
     mut commands: Commands,
-    equiped_weapon: Query<(&Weapon, Entity), With<AttachedTo>>,
-    asset_server: Res<AssetServer>,
+    mut inventories: Query<(&mut WeaponInventory, Option<&EquippedWeapon>)>,
+    attachments: Query<&Attachments>,
+    weapons: Query<&Weapon>,
+    definitions: Res<Assets<WeaponDefinition>>,
 ) {
-    let attachment_scene = asset_server.load(GltfAssetLabel::Scene(0).from_asset(PISTOL_PATH));
+    let EquipWeapon { entity, slot } = *equip_weapon.event();
 
-    for (weapon, entity) in equiped_weapon {
-        if weapon.kind == equip_weapon.event().kind {
-            info!("already equip weapon {:?}", weapon.kind);
-            return;
-        }
-        commands.entity(entity).despawn();
+    let Ok((mut inventory, equipped)) = inventories.get_mut(entity) else {
+        warn!("{entity} has no weapon inventory");
+        return;
+    };
+
+    if inventory.equipped == Some(slot) {
+        commands.trigger(HolsterWeapon::new(entity));
+        return;
     }
 
+    let Some(handle) = inventory.slot(slot).cloned() else {
+        info!("weapon slot {slot} is empty");
+        return;
+    };
+
+    let Some(definition) = definitions.get(&handle) else {
+        warn!("weapon of slot {slot} is not loaded yet");
+        return;
+    };
+
+    detach_weapons(&mut commands, entity, &attachments, &weapons);
+    inventory.stash_rounds(equipped);
+
+    info!("equip weapon {} from slot {slot}", definition.name);
+    let rounds = inventory
+        .rounds(slot)
+        .unwrap_or(definition.stats.magazine_size);
     commands
-        .entity(equip_weapon.entity)
+        .entity(entity)
+        .insert(EquippedWeapon::new(definition, rounds))
         .attach_scene_with_extras(
-            attachment_scene,
+            definition.scene.clone(),
             Weapon {
-                kind: WeaponKind::Pistol,
+                definition: handle,
+                slot,
             },
         );
+    inventory.equipped = Some(slot);
+}
+
+pub fn holster_weapon(
+    holster_weapon: On<HolsterWeapon>,
+    mut commands: Commands,
+    mut inventories: Query<(&mut WeaponInventory, Option<&EquippedWeapon>)>,
+    attachments: Query<&Attachments>,
+    weapons: Query<&Weapon>,
+) {
+    let entity = holster_weapon.event().entity;
+
+    detach_weapons(&mut commands, entity, &attachments, &weapons);
+    commands.entity(entity).remove::<EquippedWeapon>();
+
+    if let Ok((mut inventory, equipped)) = inventories.get_mut(entity) {
+        inventory.stash_rounds(equipped);
+        if let Some(slot) = inventory.equipped.take() {
+            info!("holster weapon of slot {slot}");
+        }
+    }
 }
@@ -0,0 +1,186 @@
+//! Game tuning loaded from `waltz/config/config.game.toml`.
+//!
+//! The file is loaded as an asset, so with bevy's `file_watcher` feature enabled any edit is
+//! picked up and re-applied to [`CameraConfig`] and [`PlayerConfig`] while the game is running.
+//! A malformed file never overwrites the values currently in use, the error is reported instead.
+use std::fmt;
+
+use bevy::{
+    asset::{AssetLoader, LoadContext, io::Reader},
+    prelude::*,
+};
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+
+use crate::{camera::config::CameraConfig, character::config::PlayerConfig};
+
+const GAME_CONFIG_PATH: &str = "waltz/config/config.game.toml";
+
+#[derive(Asset, Clone, PartialEq, Reflect, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct GameConfig {
+    pub(crate) camera: CameraConfig,
+    pub(crate) player: PlayerConfig,
+}
+
+/// Keeps the config asset alive, so the file watcher keeps reloading it.
+#[derive(Resource)]
+pub(crate) struct GameConfigHandle(Handle<GameConfig>);
+
+#[derive(Debug)]
+pub(crate) enum GameConfigError {
+    Io(std::io::Error),
+    Utf8(std::str::Utf8Error),
+    /// `section` is the toml table the error was found in, the root table is named `<root>`
+    Parse {
+        section: String,
+        line: usize,
+        message: String,
+    },
+}
+
+impl fmt::Display for GameConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GameConfigError::Io(err) => write!(f, "failed to read config file: {err}"),
+            GameConfigError::Utf8(err) => write!(f, "config file is not valid utf-8: {err}"),
+            GameConfigError::Parse {
+                section,
+                line,
+                message,
+            } => write!(f, "[{section}] (line {line}): {message}"),
+        }
+    }
+}
+
+impl std::error::Error for GameConfigError {}
+
+impl From<std::io::Error> for GameConfigError {
+    fn from(err: std::io::Error) -> Self {
+        GameConfigError::Io(err)
+    }
+}
+
+impl GameConfigError {
+    fn from_toml(source: &str, err: toml::de::Error) -> Self {
+        let offset = err.span().map(|span| span.start).unwrap_or_default();
+        let (section, line) = locate_section(source, offset);
+        GameConfigError::Parse {
+            section,
+            line,
+            message: err.message().to_string(),
+        }
+    }
+}
+
+/// Finds the table header enclosing `offset` and the 1-based line of `offset`.
+fn locate_section(source: &str, offset: usize) -> (String, usize) {
+    let mut section = String::from("<root>");
+    let mut line = 1;
+    let mut position = 0;
+
+    for text in source.split_inclusive('\n') {
+        let trimmed = text.trim();
+        if trimmed.starts_with('[') {
+            if let Some(end) = trimmed.find(']') {
+                section = trimmed[1..end].trim_matches(['[', ' ']).to_string();
+            }
+        }
+
+        if offset < position + text.len() {
+            break;
+        }
+
+        position += text.len();
+        line += 1;
+    }
+
+    (section, line)
+}
+
+/// Reads a toml file from an asset reader, errors point to the table and line they were found in.
+pub(crate) async fn read_toml<T: DeserializeOwned>(
+    reader: &mut dyn Reader,
+) -> Result<T, GameConfigError> {
+    let mut bytes = Vec::new();
+    reader.read_to_end(&mut bytes).await?;
+    let source = std::str::from_utf8(&bytes).map_err(GameConfigError::Utf8)?;
+
+    toml::from_str(source).map_err(|err| GameConfigError::from_toml(source, err))
+}
+
+#[derive(Default, TypePath)]
+struct GameConfigLoader;
+
+impl AssetLoader for GameConfigLoader {
+    type Asset = GameConfig;
+    type Settings = ();
+    type Error = GameConfigError;
+
+    async fn load(
+        &self,
+        reader: &mut dyn Reader,
+        _settings: &(),
+        _load_context: &mut LoadContext<'_>,
+    ) -> Result<Self::Asset, Self::Error> {
+        read_toml(reader).await
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["game.toml"]
+    }
+}
+
+pub(crate) fn plugin(app: &mut App) {
+    app.init_asset::<GameConfig>()
+        .register_asset_loader(GameConfigLoader)
+        .add_systems(Startup, load_game_config)
+        .add_systems(Update, (apply_game_config, report_game_config_error));
+}
+
+fn load_game_config(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands.insert_resource(GameConfigHandle(asset_server.load(GAME_CONFIG_PATH)));
+}
+
+/// Copies the config into the resources every time the file is (re)loaded.
+pub(crate) fn apply_game_config(
+    mut events: MessageReader<AssetEvent<GameConfig>>,
+    game_configs: Res<Assets<GameConfig>>,
+    mut camera_config: ResMut<CameraConfig>,
+    mut player_config: ResMut<PlayerConfig>,
+) {
+    for event in events.read() {
+        match event {
+            AssetEvent::Added { id } | AssetEvent::Modified { id } => {
+                let Some(game_config) = game_configs.get(*id) else {
+                    continue;
+                };
+
+                info!("apply game config from {GAME_CONFIG_PATH}");
+                camera_config.set_if_neq(game_config.camera.clone());
+                player_config.set_if_neq(game_config.player.clone());
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Whether the config file was read, or failed to, so the config resources hold their values
+/// for the session start.
+pub(crate) fn game_config_settled(
+    handle: Option<Res<GameConfigHandle>>,
+    asset_server: Res<AssetServer>,
+) -> bool {
+    handle.is_some_and(|handle| {
+        let state = asset_server.load_state(&handle.0);
+        state.is_loaded() || state.is_failed()
+    })
+}
+
+fn report_game_config_error(mut events: MessageReader<AssetLoadFailedEvent<GameConfig>>) {
+    for event in events.read() {
+        error!(
+            "{} is invalid, keep the current config: {}",
+            event.path, event.error
+        );
+    }
+}
@@ -42,12 +42,27 @@ struct CameraOrbitAction;
 #[action_output(Vec2)]
 struct CameraZoomAction;
 
+#[derive(Debug, InputAction)]
+#[action_output(bool)]
+struct CameraCycleKindAction;
+
+#[derive(Debug, InputAction)]
+#[action_output(bool)]
+struct CameraAimAction;
+
+#[derive(Debug, InputAction)]
+#[action_output(bool)]
+struct CameraSwapShoulderAction;
+
 pub fn plugin(app: &mut App) {
     app.add_observer(anchor_camera_to_chracter)
         .add_input_context::<CameraCtrl>()
         .add_observer(setup_camera_ctrl_bind)
         .add_observer(orbit_camera)
-        .add_observer(zoom_camera);
+        .add_observer(zoom_camera)
+        .add_observer(cycle_camera_kind)
+        .add_observer(toggle_camera_aim)
+        .add_observer(swap_camera_shoulder);
 }
 
 pub fn anchor_camera_to_chracter(
@@ -80,7 +95,10 @@ fn setup_camera_ctrl_bind(trigger: On<Add, WaltzCamera>, mut commands: Commands)
                     Bidirectional::new(GamepadButton::DPadUp, GamepadButton::DPadDown),
                 )),
 
-            )
+            ),
+            (Action::<CameraCycleKindAction>::new(), bindings![KeyCode::KeyV, GamepadButton::RightThumb]),
+            (Action::<CameraAimAction>::new(), bindings![MouseButton::Right, GamepadButton::LeftTrigger2]),
+            (Action::<CameraSwapShoulderAction>::new(), bindings![KeyCode::KeyX, GamepadButton::Select])
         ]),
     ));
 }
@@ -106,8 +124,8 @@ fn orbit_camera(
 
 fn zoom_camera(
     trigger: On<Fire<CameraZoomAction>>,
-    mut commands: Commands,
-    primary_window: Single<&mut CursorOptions, With<PrimaryWindow>>,
+    mut zooms: MessageWriter<CameraZoom>,
+    primary_window: Single<&CursorOptions, With<PrimaryWindow>>,
 ) {
     let cursor_options = primary_window.into_inner();
     if cursor_options.grab_mode == CursorGrabMode::None {
@@ -116,11 +134,33 @@ fn zoom_camera(
 
     debug!("trigger is {}", trigger.value);
 
-    commands.spawn(CameraZoom {
+    // scrolling up pulls the camera closer to the anchor
+    zooms.write(CameraZoom {
         zoom: if trigger.value.y > 0.0 {
             CameraZoomKind::ZoomIn
         } else {
-            CameraZoomKind::ZoomIn
+            CameraZoomKind::ZoomOut
         },
+        amount: trigger.value.y.abs(),
     });
 }
+
+fn cycle_camera_kind(
+    _trigger: On<Start<CameraCycleKindAction>>,
+    mut camera: Single<&mut WaltzCamera>,
+) {
+    camera.cycle_kind();
+    info!("switch camera kind to {:?}", camera.kind);
+}
+
+fn toggle_camera_aim(_trigger: On<Start<CameraAimAction>>, mut camera: Single<&mut WaltzCamera>) {
+    camera.toggle_aim();
+    info!("camera aiming: {}", camera.aiming);
+}
+
+fn swap_camera_shoulder(
+    _trigger: On<Start<CameraSwapShoulderAction>>,
+    mut camera: Single<&mut WaltzCamera>,
+) {
+    camera.swap_shoulder();
+}
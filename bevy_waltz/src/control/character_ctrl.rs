@@ -2,25 +2,49 @@ use bevy::ecs::query::QueryData;
 use bevy::ecs::system::Query;
 use bevy::input::keyboard::KeyCode;
 use bevy::prelude::*;
+use bevy::window::{CursorGrabMode, CursorOptions, PrimaryWindow};
 use bevy_enhanced_input::prelude::*;
-use bevy_tnua::control_helpers::TnuaActionsCounter;
-use bevy_tnua::math::AsF32;
+use bevy_tnua::builtins::{TnuaBuiltinClimb, TnuaBuiltinDash, TnuaBuiltinWallSlide};
+use bevy_tnua::control_helpers::{
+    TnuaActionsCounter, TnuaBlipReuseAvoidance, TnuaSimpleFallThroughPlatformsHelper,
+};
+use bevy_tnua::math::{AdjustPrecision, AsF32, Float, Vector3};
 use bevy_tnua::prelude::*;
+use bevy_tnua::radar_lens::{TnuaBlipSpatialRelation, TnuaRadarLens};
+use bevy_tnua::{
+    TnuaGhostOverwrites, TnuaGhostPlatform, TnuaGhostSensor, TnuaObstacleRadar,
+    builtins::TnuaBuiltinCrouch,
+};
+use bevy_tnua_avian3d::TnuaSpatialExtAvian3d;
 
-use crate::character::config::CharacterMotionConfig;
+use crate::camera::CrosshairTarget;
+use crate::character::config::{CharacterMotionConfig, FallingThroughControlScheme};
 use crate::character::{
-    EquipWeapon, WaltzAirActionSlots, WaltzTnuaCtrlScheme, WaltzTnuaCtrlSchemeActionDiscriminant,
-    WeaponKind,
+    EquipWeapon, EquippedWeapon, FireWeapon, HolsterWeapon, ReloadWeapon, RootMotion,
+    WaltzAirActionSlots, WaltzTnuaCtrlScheme, WaltzTnuaCtrlSchemeActionDiscriminant,
+    WaltzTnuaCtrlSchemeActionState, WeaponInventory, character_facing, tnua_forward,
 };
+use crate::gp::{ActivatePower, PowerSlots};
 use crate::level_switch::Climable;
+use crate::lock_on::{CycleLockOn, LockOn, ToggleLockOn};
+use crate::utils::Vec3Ext;
 use crate::{WaltzCamera, WaltzPlayer};
 
 #[derive(Component, Reflect, Default)]
 #[reflect(Component)]
 struct AccumulatedInput {
     last_move: Option<Vec3>,
+    /// crouch is held, cleared once it was applied
+    crouch: bool,
+    /// crouch was pressed since it was last applied
+    crouch_started: bool,
 }
 
+/// The normal of the wall the character slides on, a jump pushes away from it.
+#[derive(Component, Reflect, Default)]
+#[reflect(Component)]
+struct WallSlideNormal(Option<Dir3>);
+
 #[derive(QueryData)]
 pub struct ObstacleQueryHelper {
     pub climbable: Has<Climable>,
@@ -35,12 +59,28 @@ pub fn plugin(app: &mut App) {
     app.add_observer(accumulate_movement);
 
     app.add_observer(apply_jump);
-    app.add_observer(set_weapon);
+    app.add_observer(accumulate_crouch);
+    app.add_observer(accumulate_crouch_start);
+    app.add_observer(apply_dash);
+    app.add_observer(select_weapon_slot::<WeaponSlot1>);
+    app.add_observer(select_weapon_slot::<WeaponSlot2>);
+    app.add_observer(select_weapon_slot::<WeaponSlot3>);
+    app.add_observer(cycle_weapon);
+    app.add_observer(holster_weapon);
+    app.add_observer(press_trigger);
+    app.add_observer(hold_trigger);
+    app.add_observer(reload_weapon);
+    app.add_observer(toggle_lock_on);
+    app.add_observer(cycle_lock_on);
+    app.add_observer(activate_power_slot::<PowerSlot1>);
+    app.add_observer(activate_power_slot::<PowerSlot2>);
 
     app.add_systems(
         Update,
         // apply_character_control.in_set(TnuaUserControlsSystemSet),
-        apply_tnua_ctrl.in_set(TnuaUserControlsSystems),
+        (apply_tnua_ctrl, apply_crouch)
+            .chain()
+            .in_set(TnuaUserControlsSystems),
     );
 
     // app.add_systems(
@@ -62,7 +102,86 @@ struct Jump;
 
 #[derive(Debug, InputAction)]
 #[action_output(bool)]
-struct SetWeapon;
+struct Crouch;
+
+#[derive(Debug, InputAction)]
+#[action_output(bool)]
+struct Dash;
+
+#[derive(Debug, InputAction)]
+#[action_output(bool)]
+struct WeaponSlot1;
+
+#[derive(Debug, InputAction)]
+#[action_output(bool)]
+struct WeaponSlot2;
+
+#[derive(Debug, InputAction)]
+#[action_output(bool)]
+struct WeaponSlot3;
+
+/// Maps a weapon slot action to its inventory slot.
+trait WeaponSlotAction: InputAction {
+    const SLOT: usize;
+}
+
+impl WeaponSlotAction for WeaponSlot1 {
+    const SLOT: usize = 0;
+}
+
+impl WeaponSlotAction for WeaponSlot2 {
+    const SLOT: usize = 1;
+}
+
+impl WeaponSlotAction for WeaponSlot3 {
+    const SLOT: usize = 2;
+}
+
+/// Holding the gamepad button cycles the weapon, tapping it reloads.
+#[derive(Debug, InputAction)]
+#[action_output(bool)]
+struct CycleWeapon;
+
+#[derive(Debug, InputAction)]
+#[action_output(bool)]
+struct HolsterWeaponAction;
+
+#[derive(Debug, InputAction)]
+#[action_output(bool)]
+struct FireAction;
+
+#[derive(Debug, InputAction)]
+#[action_output(bool)]
+struct ReloadAction;
+
+#[derive(Debug, InputAction)]
+#[action_output(bool)]
+struct LockOnAction;
+
+#[derive(Debug, InputAction)]
+#[action_output(f32)]
+struct CycleLockOnAction;
+
+#[derive(Debug, InputAction)]
+#[action_output(bool)]
+struct PowerSlot1;
+
+#[derive(Debug, InputAction)]
+#[action_output(bool)]
+struct PowerSlot2;
+
+/// Maps a power action to its slot in [`PowerSlots`].
+trait PowerSlotAction: InputAction {
+    const SLOT: usize;
+}
+
+impl PowerSlotAction for PowerSlot1 {
+    const SLOT: usize = 0;
+}
+
+impl PowerSlotAction for PowerSlot2 {
+    const SLOT: usize = 1;
+}
 
 fn setup_character_ctrl_bind(add: On<Add, WaltzPlayer>, mut commands: Commands) {
     info!("setup player bind");
@@ -71,7 +190,25 @@ fn setup_character_ctrl_bind(add: On<Add, WaltzPlayer>, mut commands: Commands)
         actions!(CharacterCtrl[
             (Action::<Move>::new(), Bindings::spawn((Cardinal::wasd_keys(), Axial::left_stick()))),
             (Action::<Jump>::new(), bindings![KeyCode::Space, GamepadButton::West]),
-            (Action::<SetWeapon>::new(), bindings![KeyCode::Digit1, GamepadButton::North])
+            (Action::<Crouch>::new(), bindings![KeyCode::ControlLeft, KeyCode::KeyC, GamepadButton::South]),
+            (Action::<Dash>::new(), bindings![KeyCode::ShiftLeft, GamepadButton::East]),
+            (Action::<WeaponSlot1>::new(), bindings![KeyCode::Digit1]),
+            (Action::<WeaponSlot2>::new(), bindings![KeyCode::Digit2]),
+            (Action::<WeaponSlot3>::new(), bindings![KeyCode::Digit3]),
+            (Action::<CycleWeapon>::new(), bindings![(GamepadButton::North, Hold::new(0.4))]),
+            (Action::<HolsterWeaponAction>::new(), bindings![KeyCode::KeyH]),
+            (Action::<FireAction>::new(), bindings![MouseButton::Left, GamepadButton::RightTrigger2]),
+            (Action::<ReloadAction>::new(), bindings![KeyCode::KeyR, (GamepadButton::North, Tap::new(0.25))]),
+            (Action::<LockOnAction>::new(), bindings![KeyCode::Tab, MouseButton::Middle, GamepadButton::LeftThumb]),
+            (
+                Action::<CycleLockOnAction>::new(),
+                Bindings::spawn((
+                    Bidirectional::new(KeyCode::KeyE, KeyCode::KeyQ),
+                    Bidirectional::new(GamepadButton::DPadRight, GamepadButton::DPadLeft),
+                )),
+            ),
+            (Action::<PowerSlot1>::new(), bindings![KeyCode::KeyF, GamepadButton::LeftTrigger]),
+            (Action::<PowerSlot2>::new(), bindings![KeyCode::KeyG, GamepadButton::RightTrigger])
         ]),
     ));
 }
@@ -80,7 +217,7 @@ fn setup_character_accumulated(trigger: On<Add, WaltzPlayer>, mut commands: Comm
     info!("setup player accumulated");
     commands
         .entity(trigger.entity)
-        .insert(AccumulatedInput::default());
+        .insert((AccumulatedInput::default(), WallSlideNormal::default()));
 }
 
 // fn bind_character_ctrl_action(
@@ -129,8 +266,14 @@ fn clear_accumulated_input(mut accumulated_inputs: Query<&mut AccumulatedInput>)
 struct TnuaCtrlQuery {
     controller: &'static mut TnuaController<WaltzTnuaCtrlScheme>,
     accumulated_input: &'static AccumulatedInput,
+    transform: &'static Transform,
+    lock_on: Option<&'static LockOn>,
     air_actions_counter: &'static mut TnuaActionsCounter<WaltzAirActionSlots>,
     motion_config: &'static CharacterMotionConfig,
+    obstacle_radar: &'static TnuaObstacleRadar,
+    blip_reuse_avoidance: &'static mut TnuaBlipReuseAvoidance<WaltzTnuaCtrlScheme>,
+    wall_slide_normal: &'static mut WallSlideNormal,
+    root_motion: Option<&'static RootMotion>,
 }
 
 #[derive(QueryData)]
@@ -142,6 +285,9 @@ struct TnuaCameraQuery {
 fn apply_tnua_ctrl(
     tnua_ctrl_query: Single<TnuaCtrlQuery>,
     camera_query: Option<Single<TnuaCameraQuery>>,
+    targets: Query<&GlobalTransform>,
+    spatial_ext: TnuaSpatialExtAvian3d,
+    obstacle_query: Query<ObstacleQueryHelper>,
 ) {
     let mut tnua_ctrl = tnua_ctrl_query.into_inner();
     let (controller, accumulated_input, motion_config) = (
@@ -153,10 +299,14 @@ fn apply_tnua_ctrl(
     controller.initiate_action_feeding();
 
     let mut yaw = 0.0;
+    let mut aim_forward = None;
     let last_move = accumulated_input.last_move.unwrap_or_default();
     if let Some(tnua_camera) = camera_query {
         let (transform, waltz_camera) = (tnua_camera.transform, tnua_camera.waltz_camera);
         yaw = transform.rotation.to_euler(EulerRot::YXZ).0;
+        if waltz_camera.is_aiming() {
+            aim_forward = Some(transform.forward().horizontal());
+        }
         debug!(
             "camera position: {:?}, target: {}, yaw: {}, last_move: {}",
             transform, waltz_camera.target, yaw, last_move
@@ -175,11 +325,218 @@ fn apply_tnua_ctrl(
         direction = Vec3::ZERO;
     }
 
+    // While locked on, the character strafes and keeps facing the target.
+    let lock_on_target = tnua_ctrl
+        .lock_on
+        .and_then(|lock_on| lock_on.target)
+        .and_then(|target| targets.get(target).ok())
+        .map(|target| (target.translation() - tnua_ctrl.transform.translation).horizontal());
+
+    // While aiming, the character faces the camera yaw instead.
+    let desired_forward = match (aim_forward, lock_on_target) {
+        (Some(forward), _) => Dir3::new(-forward).ok(),
+        (None, Some(to_target)) => Dir3::new(-to_target).ok(),
+        (None, None) => Dir3::new(-direction.f32()).ok(),
+    };
+
+    let speed_factor = if controller.action_discriminant()
+        == Some(WaltzTnuaCtrlSchemeActionDiscriminant::Crouch)
+    {
+        motion_config.crouch_speed_factor
+    } else {
+        1.0
+    };
+
+    // While the animation state plays with root motion, the clip sets the speed and the input
+    // only steers, the root turn applies when there is no input.
+    let (desired_motion, desired_forward) = match tnua_ctrl
+        .root_motion
+        .filter(|root_motion| root_motion.active)
+    {
+        Some(root_motion) if direction == Vec3::ZERO => (
+            Vec3::ZERO,
+            desired_forward.or_else(|| {
+                let turn = Quat::from_rotation_y(root_motion.yaw);
+                Dir3::new(turn * tnua_ctrl.transform.forward()).ok()
+            }),
+        ),
+        Some(root_motion) => (root_motion.velocity.horizontal(), desired_forward),
+        None => (
+            direction * speed_factor * motion_config.speed,
+            desired_forward,
+        ),
+    };
+
     // Feed TnuaBuiltinWalk every frame.
     controller.basis = TnuaBuiltinWalk {
-        desired_motion: direction * motion_config.speed,
-        desired_forward: Dir3::new(-direction.f32()).ok(),
+        desired_motion,
+        desired_forward,
     };
+
+    tnua_ctrl
+        .blip_reuse_avoidance
+        .update(&tnua_ctrl.controller, tnua_ctrl.obstacle_radar);
+
+    // w climbs up and s climbs down, whatever the camera yaw is
+    let climb_input = -last_move.z;
+    // a climbing clip with root motion sets the pace, the input only the direction, a clip
+    // climbing in place keeps the configured speed
+    let climb_speed = match tnua_ctrl
+        .root_motion
+        .filter(|root_motion| root_motion.active && root_motion.velocity.y.abs() > 1e-3)
+    {
+        Some(_) if climb_input == 0.0 => 0.0,
+        Some(root_motion) => root_motion.velocity.y.abs().copysign(climb_input),
+        None => motion_config.climb_speed * climb_input,
+    };
+    tnua_ctrl.wall_slide_normal.0 = feed_obstacle_actions(
+        &mut tnua_ctrl.controller,
+        &TnuaRadarLens::new(tnua_ctrl.obstacle_radar, &spatial_ext),
+        &tnua_ctrl.blip_reuse_avoidance,
+        &obstacle_query,
+        direction,
+        climb_speed,
+    );
+}
+
+/// Starts or keeps climbing a [`Climable`] obstacle the character walks into, and slides down the
+/// walls it jumps against. Returns the normal of the wall the character slides on.
+///
+/// `climb_speed` is the vertical speed of the climb, up when positive.
+fn feed_obstacle_actions(
+    controller: &mut TnuaController<WaltzTnuaCtrlScheme>,
+    radar_lens: &TnuaRadarLens<TnuaSpatialExtAvian3d>,
+    blip_reuse_avoidance: &TnuaBlipReuseAvoidance<WaltzTnuaCtrlScheme>,
+    obstacle_query: &Query<ObstacleQueryHelper>,
+    direction: Vec3,
+    climb_speed: f32,
+) -> Option<Dir3> {
+    // how far above or below the anchor the climbable obstacle is probed for its end
+    const LOOK_ABOVE_OR_BELOW: Float = 5.0;
+
+    let direction = direction.adjust_precision();
+    let airborne = matches!(controller.is_airborne(), Ok(true));
+
+    let (already_sliding_on, already_climbing_on) = match controller.current_action.as_ref() {
+        Some(WaltzTnuaCtrlSchemeActionState::WallSlide(_, entity)) => (Some(*entity), None),
+        Some(WaltzTnuaCtrlSchemeActionState::Climb(_, entity, initiation_direction)) => {
+            (None, Some((*entity, *initiation_direction)))
+        }
+        _ => (None, None),
+    };
+
+    let mut wall_slide_normal = None;
+
+    for blip in radar_lens.iter_blips() {
+        let entity = blip.entity();
+        let climbable = obstacle_query
+            .get(entity)
+            .is_ok_and(|obstacle| obstacle.climbable);
+
+        if climbable && !blip_reuse_avoidance.should_avoid(entity) {
+            let direction_to_anchor = -blip
+                .normal_from_closest_point()
+                .reject_from_normalized(Vector3::Y);
+
+            if let Some((climbing_on, initiation_direction)) = already_climbing_on {
+                if climbing_on != entity {
+                    continue;
+                }
+
+                // keep pushing towards the obstacle to stay on it at the bottom
+                let initiation_direction = if 0.5 < direction.dot(initiation_direction) {
+                    initiation_direction
+                } else {
+                    Vector3::ZERO
+                };
+
+                let mut climb = TnuaBuiltinClimb {
+                    anchor: blip.closest_point().get(),
+                    desired_vec_to_anchor: Vector3::ZERO,
+                    desired_forward: Dir3::new(-direction_to_anchor.f32()).ok(),
+                    desired_climb_velocity: climb_speed.adjust_precision() * Vector3::Y,
+                    ..Default::default()
+                };
+
+                if climb_speed < 0.0 {
+                    if airborne {
+                        let extent =
+                            blip.probe_extent_from_closest_point(-Dir3::Y, LOOK_ABOVE_OR_BELOW);
+                        if extent < 0.9 * LOOK_ABOVE_OR_BELOW {
+                            climb.hard_stop_down =
+                                Some(blip.closest_point().get() - extent * Vector3::Y);
+                        }
+                    } else if initiation_direction == Vector3::ZERO {
+                        // climbed all the way down, let go
+                        continue;
+                    } else {
+                        climb.desired_climb_velocity = Vector3::ZERO;
+                    }
+                } else if 0.0 < climb_speed {
+                    let extent = blip.probe_extent_from_closest_point(Dir3::Y, LOOK_ABOVE_OR_BELOW);
+                    if extent < 0.9 * LOOK_ABOVE_OR_BELOW {
+                        climb.hard_stop_up = Some(blip.closest_point().get() + extent * Vector3::Y);
+                    }
+                }
+
+                controller.action(WaltzTnuaCtrlScheme::Climb(
+                    climb,
+                    entity,
+                    initiation_direction,
+                ));
+            } else if let TnuaBlipSpatialRelation::Aeside(blip_direction) =
+                blip.spatial_relation(0.5)
+            {
+                if 0.5 < direction.dot(blip_direction.adjust_precision()) {
+                    controller.action(WaltzTnuaCtrlScheme::Climb(
+                        TnuaBuiltinClimb {
+                            anchor: blip.closest_point().get(),
+                            desired_vec_to_anchor: Vector3::ZERO,
+                            desired_forward: Dir3::new(-direction_to_anchor.f32()).ok(),
+                            ..Default::default()
+                        },
+                        entity,
+                        direction.normalize_or_zero(),
+                    ));
+                }
+            }
+        }
+
+        if !airborne || !blip.is_interactable() {
+            continue;
+        }
+
+        let TnuaBlipSpatialRelation::Aeside(blip_direction) = blip.spatial_relation(0.5) else {
+            continue;
+        };
+
+        // moving away from the wall slowly does not stop the slide
+        let dot_threshold = if already_sliding_on == Some(entity) {
+            -0.1
+        } else {
+            0.0
+        };
+
+        if dot_threshold < direction.dot(blip_direction.adjust_precision())
+            && 0.8 < blip.flat_wall_score(Dir3::Y, &[-1.0, 1.0])
+        {
+            let Ok(normal) = Dir3::new(blip.normal_from_closest_point().f32()) else {
+                continue;
+            };
+
+            controller.action(WaltzTnuaCtrlScheme::WallSlide(
+                TnuaBuiltinWallSlide {
+                    contact_point_with_wall: blip.closest_point().get(),
+                    normal,
+                    force_forward: Some(tnua_forward(blip_direction)),
+                },
+                entity,
+            ));
+            wall_slide_normal = Some(normal);
+        }
+    }
+
+    wall_slide_normal
 }
 
 /// handle jump action for walk/climp/walljump
@@ -189,12 +546,28 @@ fn apply_jump(
         &CharacterMotionConfig,
         &mut TnuaActionsCounter<WaltzAirActionSlots>,
         &mut TnuaController<WaltzTnuaCtrlScheme>,
+        &WallSlideNormal,
     )>,
 ) {
-    let (config, air_actions_counter, mut controller) = query.get_mut(jump.context).unwrap();
+    let (config, air_actions_counter, mut controller, wall_slide_normal) =
+        query.get_mut(jump.context).unwrap();
 
     let current_action_discriminant = controller.action_discriminant();
 
+    // jumping during a wall slide pushes away from the wall, holding the button keeps the wall
+    // jump going since it shares the trigger of the jump
+    if current_action_discriminant == Some(WaltzTnuaCtrlSchemeActionDiscriminant::WallSlide) {
+        if let Some(normal) = wall_slide_normal.0 {
+            controller.action(WaltzTnuaCtrlScheme::WallJump(TnuaBuiltinJump {
+                vertical_displacement: Some(2.0 * normal.adjust_precision()),
+                allow_in_air: true,
+                force_forward: Some(-normal),
+                ..Default::default()
+            }));
+            return;
+        }
+    }
+
     controller.action(WaltzTnuaCtrlScheme::Jump(TnuaBuiltinJump {
         // Jumping, like crouching, is an action that we either feed or don't. However,
         // because it can be used in midair, we want to set its `allow_in_air`. The air
@@ -220,10 +593,229 @@ fn apply_jump(
     }));
 }
 
-fn set_weapon(
-    _trigger: On<Start<SetWeapon>>,
+fn accumulate_crouch(
+    _trigger: On<Fire<Crouch>>,
+    mut accumulated_inputs: Single<&mut AccumulatedInput>,
+) {
+    accumulated_inputs.crouch = true;
+}
+
+fn accumulate_crouch_start(
+    _trigger: On<Start<Crouch>>,
+    mut accumulated_inputs: Single<&mut AccumulatedInput>,
+) {
+    accumulated_inputs.crouch_started = true;
+}
+
+#[derive(QueryData)]
+#[query_data(mutable)]
+struct FallThroughQuery {
+    controller: &'static mut TnuaController<WaltzTnuaCtrlScheme>,
+    accumulated_input: &'static mut AccumulatedInput,
+    motion_config: &'static CharacterMotionConfig,
+    falling_through: &'static FallingThroughControlScheme,
+    ghost_sensor: &'static TnuaGhostSensor,
+    ghost_overwrites: &'static mut TnuaGhostOverwrites<WaltzTnuaCtrlScheme>,
+    fall_through_helper: &'static mut TnuaSimpleFallThroughPlatformsHelper,
+}
+
+/// Crouching is fed for as long as the button is held, unless the character uses it to fall
+/// through a one-way platform, see [`FallingThroughControlScheme`].
+fn apply_crouch(
+    fall_through_query: Single<FallThroughQuery>,
+    platform_schemes: Query<&FallingThroughControlScheme, With<TnuaGhostPlatform>>,
+) {
+    let mut character = fall_through_query.into_inner();
+    let min_proximity = character.motion_config.one_way_platforms_min_proximity;
+    let crouch_pressed = std::mem::take(&mut character.accumulated_input.crouch);
+    let crouch_just_pressed = std::mem::take(&mut character.accumulated_input.crouch_started);
+
+    // the platform below may fall through its own way
+    let scheme = character
+        .ghost_sensor
+        .iter()
+        .filter(|ghost_platform| min_proximity <= ghost_platform.proximity)
+        .find_map(|ghost_platform| platform_schemes.get(ghost_platform.entity).ok())
+        .unwrap_or(character.falling_through);
+
+    // One-way platforms only carry the character when the ghost sensor output overwrites the
+    // ground sensor, platforms above the character never reach the minimal proximity.
+    let ground_overwrite = &mut character.ghost_overwrites.ground;
+    let crouch = match scheme {
+        FallingThroughControlScheme::JumpThroughOnly => {
+            if let Some(ghost_platform) = character
+                .ghost_sensor
+                .iter()
+                .find(|ghost_platform| min_proximity <= ghost_platform.proximity)
+            {
+                ground_overwrite.set(ghost_platform);
+            }
+            crouch_pressed
+        }
+        FallingThroughControlScheme::WithoutHelper => {
+            let relevant_platform = character
+                .ghost_sensor
+                .iter()
+                .find(|ghost_platform| min_proximity <= ghost_platform.proximity);
+
+            if crouch_pressed {
+                // drop through while the platform is below, crouch once on solid ground
+                relevant_platform.is_none()
+            } else {
+                if let Some(ghost_platform) = relevant_platform {
+                    ground_overwrite.set(ghost_platform);
+                }
+                false
+            }
+        }
+        FallingThroughControlScheme::SingleFall => {
+            let mut handler = character.fall_through_helper.with(
+                ground_overwrite,
+                character.ghost_sensor,
+                min_proximity,
+            );
+            if crouch_pressed {
+                !handler.try_falling(crouch_just_pressed)
+            } else {
+                handler.dont_fall();
+                false
+            }
+        }
+        FallingThroughControlScheme::KeepFalling => {
+            let mut handler = character.fall_through_helper.with(
+                ground_overwrite,
+                character.ghost_sensor,
+                min_proximity,
+            );
+            if crouch_pressed {
+                !handler.try_falling(true)
+            } else {
+                handler.dont_fall();
+                false
+            }
+        }
+    };
+
+    if crouch {
+        character
+            .controller
+            .action(WaltzTnuaCtrlScheme::Crouch(TnuaBuiltinCrouch::default()));
+    }
+}
+
+/// Dashes along the movement, or along the facing when standing still.
+fn apply_dash(
+    dash: On<Start<Dash>>,
+    mut query: Query<(
+        &CharacterMotionConfig,
+        &Transform,
+        &TnuaActionsCounter<WaltzAirActionSlots>,
+        &mut TnuaController<WaltzTnuaCtrlScheme>,
+    )>,
+) {
+    let Ok((config, transform, air_actions_counter, mut controller)) = query.get_mut(dash.context)
+    else {
+        return;
+    };
+
+    let direction = Dir3::new(controller.basis.desired_motion.f32())
+        .unwrap_or_else(|_| character_facing(transform));
+
+    controller.action(WaltzTnuaCtrlScheme::Dash(TnuaBuiltinDash {
+        displacement: direction.adjust_precision() * config.dash_distance,
+        desired_forward: Some(tnua_forward(direction)),
+        allow_in_air: air_actions_counter.count_for(WaltzTnuaCtrlSchemeActionDiscriminant::Dash)
+            <= config.actions_in_air,
+    }));
+}
+
+fn select_weapon_slot<A: WeaponSlotAction>(
+    _trigger: On<Start<A>>,
+    mut commands: Commands,
+    player: Single<Entity, With<WaltzPlayer>>,
+) {
+    commands.trigger(EquipWeapon::new(player.into_inner(), A::SLOT));
+}
+
+/// Cycles once the hold is released, a tap of the same button is a reload.
+fn cycle_weapon(
+    _trigger: On<Complete<CycleWeapon>>,
+    mut commands: Commands,
+    player: Single<(Entity, &WeaponInventory), With<WaltzPlayer>>,
+) {
+    let (entity, inventory) = player.into_inner();
+    if let Some(slot) = inventory.next_slot() {
+        commands.trigger(EquipWeapon::new(entity, slot));
+    }
+}
+
+fn holster_weapon(
+    _trigger: On<Start<HolsterWeaponAction>>,
     mut commands: Commands,
     player: Single<Entity, With<WaltzPlayer>>,
 ) {
-    commands.trigger(EquipWeapon::new(player.into_inner(), WeaponKind::Pistol));
+    commands.trigger(HolsterWeapon::new(player.into_inner()));
+}
+
+/// Fires a single shot, automatic weapons keep firing in [`hold_trigger`].
+fn press_trigger(
+    _trigger: On<Start<FireAction>>,
+    mut commands: Commands,
+    player: Single<(Entity, &EquippedWeapon), With<WaltzPlayer>>,
+    primary_window: Single<&CursorOptions, With<PrimaryWindow>>,
+    crosshair: Res<CrosshairTarget>,
+) {
+    let (entity, weapon) = player.into_inner();
+    if primary_window.grab_mode == CursorGrabMode::None || weapon.stats.automatic {
+        return;
+    }
+
+    commands.trigger(FireWeapon::new(entity, Some(crosshair.point)));
+}
+
+fn hold_trigger(
+    _trigger: On<Fire<FireAction>>,
+    mut commands: Commands,
+    player: Single<(Entity, &EquippedWeapon), With<WaltzPlayer>>,
+    primary_window: Single<&CursorOptions, With<PrimaryWindow>>,
+    crosshair: Res<CrosshairTarget>,
+) {
+    let (entity, weapon) = player.into_inner();
+    if primary_window.grab_mode == CursorGrabMode::None || !weapon.stats.automatic {
+        return;
+    }
+
+    commands.trigger(FireWeapon::new(entity, Some(crosshair.point)));
+}
+
+/// A tap only fires once it is released, a weapon already reloading ignores the repeated fires
+/// of a held key.
+fn reload_weapon(
+    _trigger: On<Fire<ReloadAction>>,
+    mut commands: Commands,
+    player: Single<Entity, With<WaltzPlayer>>,
+) {
+    commands.trigger(ReloadWeapon::new(player.into_inner()));
+}
+
+fn toggle_lock_on(_trigger: On<Start<LockOnAction>>, mut commands: Commands) {
+    commands.trigger(ToggleLockOn);
+}
+
+fn cycle_lock_on(trigger: On<Start<CycleLockOnAction>>, mut commands: Commands) {
+    commands.trigger(CycleLockOn(trigger.value));
+}
+
+fn activate_power_slot<A: PowerSlotAction>(
+    _trigger: On<Start<A>>,
+    mut commands: Commands,
+    player: Single<(Entity, &PowerSlots), With<WaltzPlayer>>,
+) {
+    let (entity, slots) = player.into_inner();
+    if let Some(power) = slots.0.get(A::SLOT) {
+        commands.trigger(ActivatePower {
+            entity,
+            power: *power,
+        });
+    }
 }
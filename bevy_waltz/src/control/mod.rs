@@ -38,9 +38,8 @@ pub(super) fn grab_ungrab_mouse(
             cursor_options.grab_mode = CursorGrabMode::Locked;
             cursor_options.visible = false;
         }
-    } else if keyboard.just_released(KeyCode::Escape)
-        || mouse_buttons.just_pressed(MouseButton::Left)
-    {
+    } else if keyboard.just_released(KeyCode::Escape) {
+        // the left mouse button fires while the cursor is grabbed
         debug!("cursor unlock");
         cursor_options.grab_mode = CursorGrabMode::None;
         cursor_options.visible = true;
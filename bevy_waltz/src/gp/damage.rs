@@ -0,0 +1,418 @@
+//! Health, damage and armor.
+//!
+//! A [`Damage`] event is triggered on the entity that was hit and propagates towards the one that
+//! owns the [`Health`], through the hierarchy or through [`AttachedTo`]. Every [`Armor`] piece on
+//! the way absorbs a part of it, e.g. a shot on the helmet is reduced by the helmet first and then
+//! by the armor of the wearer itself. Once nothing is left the propagation stops.
+use bevy::{
+    ecs::{query::QueryData, traversal::Traversal},
+    prelude::*,
+};
+use bone_attachments::relationship::AttachedTo;
+use serde::{Deserialize, Serialize};
+
+use crate::character::{Hit, WaltzPlayer};
+
+pub struct DamagePlugin;
+
+impl Plugin for DamagePlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<DamageKind>()
+            .register_type::<Health>()
+            .register_type::<Armor>()
+            .register_type::<Dead>()
+            .register_type::<RespawnAfter>()
+            .add_observer(damage_from_hit)
+            .add_observer(resolve_damage)
+            .add_observer(schedule_respawn)
+            .add_observer(respawn)
+            .add_observer(setup_player_health)
+            .add_systems(Update, (regenerate_health, tick_respawn_timers));
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Reflect, Serialize, Deserialize, Default)]
+#[reflect(Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DamageKind {
+    #[default]
+    Physical,
+    Fire,
+    Electric,
+    Poison,
+}
+
+impl DamageKind {
+    pub const ALL: [DamageKind; 4] = [
+        DamageKind::Physical,
+        DamageKind::Fire,
+        DamageKind::Electric,
+        DamageKind::Poison,
+    ];
+}
+
+#[derive(Component, Debug, Clone, PartialEq, Reflect)]
+#[reflect(Component)]
+pub struct Health {
+    pub current: f32,
+    pub max: f32,
+    /// health restored per second
+    pub regen: f32,
+    /// seconds without damage before the regeneration starts
+    pub regen_delay: f32,
+    /// seconds since the last damage
+    since_damage: f32,
+}
+
+impl Health {
+    pub fn new(max: f32) -> Self {
+        Self {
+            current: max,
+            max,
+            regen: 0.0,
+            regen_delay: 0.0,
+            since_damage: 0.0,
+        }
+    }
+
+    pub fn with_regen(mut self, regen: f32, regen_delay: f32) -> Self {
+        self.regen = regen;
+        self.regen_delay = regen_delay;
+        self
+    }
+
+    pub fn fraction(&self) -> f32 {
+        if self.max > 0.0 {
+            self.current / self.max
+        } else {
+            0.0
+        }
+    }
+}
+
+/// Absorbs a flat amount of every damage of the kinds it protects against.
+///
+/// An armor piece is a child of the wearer or attached to it, the wearer may carry an armor
+/// itself, which then applies to every damage that reaches it.
+#[derive(Component, Debug, Clone, PartialEq, Reflect)]
+#[reflect(Component)]
+pub struct Armor {
+    pub absorb: f32,
+    pub protects: Vec<DamageKind>,
+}
+
+impl Armor {
+    /// An armor protecting against every damage kind.
+    pub fn new(absorb: f32) -> Self {
+        Self {
+            absorb,
+            protects: DamageKind::ALL.to_vec(),
+        }
+    }
+
+    pub fn against(absorb: f32, protects: impl IntoIterator<Item = DamageKind>) -> Self {
+        Self {
+            absorb,
+            protects: protects.into_iter().collect(),
+        }
+    }
+}
+
+/// Marks an entity whose health was depleted, it ignores damage until it respawns.
+#[derive(Component, Debug, Clone, Copy, Reflect, Default)]
+#[reflect(Component)]
+pub struct Dead;
+
+/// Respawns the entity this many seconds after its death.
+#[derive(Component, Debug, Clone, Copy, Reflect)]
+#[reflect(Component)]
+pub struct RespawnAfter(pub f32);
+
+#[derive(Component, Debug)]
+struct RespawnTimer(Timer);
+
+/// Walks from an armor piece to its wearer, attachments are followed before the hierarchy.
+#[derive(QueryData)]
+pub struct DamageTraversal {
+    child_of: Option<&'static ChildOf>,
+    attached_to: Option<&'static AttachedTo>,
+}
+
+impl<E: EntityEvent> Traversal<E> for DamageTraversal {
+    fn traverse(item: Self::Item<'_, '_>, _event: &E) -> Option<Entity> {
+        item.attached_to
+            .map(|attached_to| **attached_to)
+            .or(item.child_of.map(ChildOf::parent))
+    }
+}
+
+/// Damages the entity, see the module documentation for the way through the armor.
+#[derive(Debug, Clone, PartialEq, EntityEvent)]
+#[entity_event(propagate = &'static DamageTraversal, auto_propagate)]
+pub struct Damage {
+    pub entity: Entity,
+    pub source: Option<Entity>,
+    pub amount: f32,
+    pub kind: DamageKind,
+}
+
+impl Damage {
+    pub fn new(entity: Entity, amount: f32, kind: DamageKind) -> Self {
+        Self {
+            entity,
+            source: None,
+            amount,
+            kind,
+        }
+    }
+
+    pub fn with_source(mut self, source: Entity) -> Self {
+        self.source = Some(source);
+        self
+    }
+}
+
+/// The health of the entity was depleted.
+#[derive(Debug, Clone, Copy, PartialEq, EntityEvent)]
+pub struct Died {
+    pub entity: Entity,
+    pub source: Option<Entity>,
+}
+
+/// Brings a dead entity back with full health.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, EntityEvent)]
+pub struct Respawn {
+    pub entity: Entity,
+}
+
+fn setup_player_health(trigger: On<Add, WaltzPlayer>, mut commands: Commands) {
+    commands
+        .entity(trigger.entity)
+        .insert((Health::new(100.0).with_regen(5.0, 4.0), RespawnAfter(2.0)));
+}
+
+/// Shots damage the collider they hit, the [`Hit`] itself propagates as well,
+/// only its first target starts the damage.
+fn damage_from_hit(hit: On<Hit>, mut commands: Commands) {
+    if hit.entity != hit.original_event_target() {
+        return;
+    }
+
+    commands.trigger(Damage::new(hit.entity, hit.damage, hit.kind).with_source(hit.source));
+}
+
+fn resolve_damage(
+    mut damage: On<Damage>,
+    mut commands: Commands,
+    armors: Query<&Armor>,
+    mut healths: Query<(&mut Health, Has<Dead>)>,
+) {
+    let entity = damage.entity;
+
+    if let Ok(armor) = armors.get(entity) {
+        if armor.protects.contains(&damage.kind) {
+            let absorbed = armor.absorb.min(damage.amount);
+            damage.amount -= absorbed;
+            debug!("{entity} absorbed {absorbed} damage");
+        }
+
+        if damage.amount <= 0.0 {
+            damage.propagate(false);
+            return;
+        }
+    }
+
+    let Ok((mut health, dead)) = healths.get_mut(entity) else {
+        return;
+    };
+
+    // the first health on the way takes the damage
+    damage.propagate(false);
+    if dead {
+        return;
+    }
+
+    health.current = (health.current - damage.amount).max(0.0);
+    health.since_damage = 0.0;
+    debug!(
+        "{entity} took {} damage, {} left",
+        damage.amount, health.current
+    );
+
+    if health.current <= 0.0 {
+        info!("{entity} died");
+        commands.entity(entity).insert(Dead);
+        commands.trigger(Died {
+            entity,
+            source: damage.source,
+        });
+    }
+}
+
+fn regenerate_health(time: Res<Time>, mut healths: Query<&mut Health, Without<Dead>>) {
+    let dt = time.delta_secs();
+
+    for mut health in &mut healths {
+        health.since_damage += dt;
+        if health.regen > 0.0
+            && health.current < health.max
+            && health.since_damage >= health.regen_delay
+        {
+            health.current = (health.current + health.regen * dt).min(health.max);
+        }
+    }
+}
+
+fn schedule_respawn(died: On<Died>, mut commands: Commands, respawns: Query<&RespawnAfter>) {
+    if let Ok(RespawnAfter(delay)) = respawns.get(died.entity) {
+        commands
+            .entity(died.entity)
+            .insert(RespawnTimer(Timer::from_seconds(*delay, TimerMode::Once)));
+    }
+}
+
+fn tick_respawn_timers(
+    time: Res<Time>,
+    mut commands: Commands,
+    mut timers: Query<(Entity, &mut RespawnTimer)>,
+) {
+    for (entity, mut timer) in &mut timers {
+        if timer.0.tick(time.delta()).just_finished() {
+            commands.entity(entity).remove::<RespawnTimer>();
+            commands.trigger(Respawn { entity });
+        }
+    }
+}
+
+fn respawn(respawn: On<Respawn>, mut commands: Commands, mut healths: Query<&mut Health>) {
+    let entity = respawn.entity;
+    if let Ok(mut health) = healths.get_mut(entity) {
+        health.current = health.max;
+        health.since_damage = 0.0;
+    }
+
+    info!("{entity} respawned");
+    commands.entity(entity).remove::<Dead>();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The entities a damage went through, in order.
+    #[derive(Resource, Default)]
+    struct DamagePath(Vec<Entity>);
+
+    #[derive(Resource, Default)]
+    struct Deaths(Vec<Entity>);
+
+    fn app() -> App {
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, DamagePlugin))
+            .init_resource::<DamagePath>()
+            .init_resource::<Deaths>()
+            .add_observer(|damage: On<Damage>, mut path: ResMut<DamagePath>| {
+                path.0.push(damage.entity);
+            })
+            .add_observer(|died: On<Died>, mut deaths: ResMut<Deaths>| {
+                deaths.0.push(died.entity);
+            });
+        app
+    }
+
+    fn damage(app: &mut App, entity: Entity, amount: f32) {
+        app.world_mut()
+            .trigger(Damage::new(entity, amount, DamageKind::Physical));
+        app.world_mut().flush();
+    }
+
+    fn health(app: &App, entity: Entity) -> f32 {
+        app.world().get::<Health>(entity).unwrap().current
+    }
+
+    #[test]
+    fn attached_pieces_are_followed_before_the_hierarchy() {
+        let mut app = app();
+        let wearer = app.world_mut().spawn(Health::new(100.0)).id();
+        let rack = app
+            .world_mut()
+            .spawn((Health::new(100.0), Armor::new(1000.0)))
+            .id();
+        let piece = app
+            .world_mut()
+            .spawn((Armor::new(10.0), ChildOf(rack), AttachedTo::from(wearer)))
+            .id();
+
+        damage(&mut app, piece, 30.0);
+
+        assert_eq!(app.world().resource::<DamagePath>().0, [piece, wearer]);
+        assert_eq!(health(&app, wearer), 80.0);
+        assert_eq!(health(&app, rack), 100.0);
+    }
+
+    #[test]
+    fn every_piece_on_the_way_absorbs() {
+        let mut app = app();
+        let wearer = app.world_mut().spawn(Health::new(100.0)).id();
+        let helmet = app
+            .world_mut()
+            .spawn((Armor::new(10.0), ChildOf(wearer)))
+            .id();
+        let visor = app
+            .world_mut()
+            .spawn((Armor::new(5.0), AttachedTo::from(helmet)))
+            .id();
+
+        damage(&mut app, visor, 30.0);
+
+        assert_eq!(
+            app.world().resource::<DamagePath>().0,
+            [visor, helmet, wearer]
+        );
+        assert_eq!(health(&app, wearer), 85.0);
+    }
+
+    #[test]
+    fn absorbed_damage_stops_propagating() {
+        let mut app = app();
+        let wearer = app.world_mut().spawn(Health::new(100.0)).id();
+        let helmet = app
+            .world_mut()
+            .spawn((Armor::new(10.0), ChildOf(wearer)))
+            .id();
+        let visor = app
+            .world_mut()
+            .spawn((Armor::new(5.0), AttachedTo::from(helmet)))
+            .id();
+
+        damage(&mut app, visor, 12.0);
+
+        assert_eq!(app.world().resource::<DamagePath>().0, [visor, helmet]);
+        assert_eq!(health(&app, wearer), 100.0);
+    }
+
+    #[test]
+    fn depleted_health_dies_once() {
+        let mut app = app();
+        let wearer = app.world_mut().spawn(Health::new(20.0)).id();
+        let helmet = app
+            .world_mut()
+            .spawn((Armor::new(5.0), ChildOf(wearer)))
+            .id();
+
+        damage(&mut app, helmet, 30.0);
+
+        assert_eq!(health(&app, wearer), 0.0);
+        assert!(app.world().entity(wearer).contains::<Dead>());
+        assert_eq!(app.world().resource::<Deaths>().0, [wearer]);
+
+        // the dead ignore damage until they respawn
+        damage(&mut app, wearer, 30.0);
+        assert_eq!(app.world().resource::<Deaths>().0, [wearer]);
+
+        app.world_mut().trigger(Respawn { entity: wearer });
+        app.world_mut().flush();
+        assert_eq!(health(&app, wearer), 20.0);
+        assert!(!app.world().entity(wearer).contains::<Dead>());
+    }
+}
@@ -1,6 +1,22 @@
 use bevy::prelude::*;
 
+mod damage;
 mod power;
+mod stats;
 
-#[derive(Event, Debug)]
-struct DemoEvent;
+pub use damage::{
+    Armor, Damage, DamageKind, DamagePlugin, Dead, Died, Health, Respawn, RespawnAfter,
+};
+
+pub use power::{
+    ActivatePower, BlastJumpPower, DashPower, Energy, Power, PowerActivated, PowerCooldowns,
+    PowerId, PowerInfo, PowerPlugin, PowerRegistry, PowerSlots, RegisterPowerExt,
+};
+
+pub use stats::{EquipGear, Gear, ModifierOp, StatKind, StatModifier, Stats, UnequipGear};
+
+pub(crate) fn plugin(app: &mut App) {
+    app.add_plugins(DamagePlugin);
+    app.add_plugins(stats::plugin);
+    app.add_plugins(PowerPlugin);
+}
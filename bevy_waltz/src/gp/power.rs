@@ -1,7 +1,440 @@
-// desc:
-// name:
-// sprite:
+//! Powers are abilities a character activates at the cost of energy, each with its own cooldown.
+//!
+//! A power implements [`Power`] and is registered once in the [`PowerRegistry`] with
+//! [`RegisterPowerExt::register_power`]. Characters refer to the registered powers by their
+//! [`PowerId`] and activate them with [`ActivatePower`].
+use std::sync::Arc;
 
-pub trait Power {
-    fn rigister(&mut self);
+use bevy::{platform::collections::HashMap, prelude::*};
+use bevy_tnua::{
+    builtins::{TnuaBuiltinDash, TnuaBuiltinKnockback},
+    prelude::TnuaController,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    character::{WaltzPlayer, WaltzTnuaCtrlScheme, character_facing},
+    gp::stats::{StatKind, Stats},
+};
+
+pub struct PowerPlugin;
+
+impl Plugin for PowerPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<PowerRegistry>()
+            .register_type::<PowerId>()
+            .register_type::<Energy>()
+            .register_type::<PowerCooldowns>()
+            .register_type::<PowerSlots>()
+            .register_power(DashPower { distance: 10.0 })
+            .register_power(BlastJumpPower {
+                shove: Vec3::new(0.0, 12.0, 6.0),
+            })
+            .add_observer(activate_power)
+            .add_observer(setup_player_powers)
+            .add_systems(Update, (tick_power_cooldowns, regenerate_energy));
+    }
+}
+
+/// What a power is and what it takes to activate it.
+#[derive(Debug, Clone, PartialEq, Reflect, Serialize, Deserialize)]
+#[reflect(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PowerInfo {
+    pub name: String,
+    pub description: String,
+    /// asset path of the icon, the power is shown by its name without one
+    #[serde(default)]
+    pub icon: Option<String>,
+    /// seconds before the power can be activated again
+    pub cooldown: f32,
+    /// energy spent on every activation
+    pub cost: f32,
+}
+
+pub trait Power: Send + Sync + 'static {
+    fn info(&self) -> PowerInfo;
+
+    /// Applies the power to the caster, cooldown and cost are already handled.
+    fn activate(&self, caster: EntityWorldMut);
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Reflect)]
+pub struct PowerId(usize);
+
+#[derive(Resource, Default)]
+pub struct PowerRegistry {
+    powers: Vec<(PowerInfo, Arc<dyn Power>)>,
+}
+
+impl PowerRegistry {
+    pub fn register(&mut self, power: impl Power) -> PowerId {
+        let info = power.info();
+        if self.find(&info.name).is_some() {
+            warn!("power {} is registered twice", info.name);
+        }
+
+        self.powers.push((info, Arc::new(power)));
+        PowerId(self.powers.len() - 1)
+    }
+
+    pub fn info(&self, id: PowerId) -> Option<&PowerInfo> {
+        self.powers.get(id.0).map(|(info, _)| info)
+    }
+
+    pub fn find(&self, name: &str) -> Option<PowerId> {
+        self.powers
+            .iter()
+            .position(|(info, _)| info.name == name)
+            .map(PowerId)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (PowerId, &PowerInfo)> {
+        self.powers
+            .iter()
+            .enumerate()
+            .map(|(index, (info, _))| (PowerId(index), info))
+    }
+
+    fn power(&self, id: PowerId) -> Option<(&PowerInfo, Arc<dyn Power>)> {
+        self.powers
+            .get(id.0)
+            .map(|(info, power)| (info, power.clone()))
+    }
+}
+
+pub trait RegisterPowerExt {
+    fn register_power(&mut self, power: impl Power) -> &mut Self;
+}
+
+impl RegisterPowerExt for App {
+    fn register_power(&mut self, power: impl Power) -> &mut Self {
+        self.world_mut()
+            .get_resource_or_init::<PowerRegistry>()
+            .register(power);
+        self
+    }
+}
+
+/// Spent by powers, restored over time.
+#[derive(Component, Debug, Clone, PartialEq, Reflect)]
+#[reflect(Component)]
+pub struct Energy {
+    pub current: f32,
+    pub max: f32,
+    /// energy restored per second
+    pub regen: f32,
+}
+
+impl Energy {
+    pub fn new(max: f32, regen: f32) -> Self {
+        Self {
+            current: max,
+            max,
+            regen,
+        }
+    }
+}
+
+/// Seconds left until each power of the character can be activated again.
+#[derive(Component, Debug, Clone, Default, Reflect)]
+#[reflect(Component)]
+pub struct PowerCooldowns(HashMap<PowerId, f32>);
+
+impl PowerCooldowns {
+    pub fn remaining(&self, power: PowerId) -> f32 {
+        self.0.get(&power).copied().unwrap_or_default()
+    }
+}
+
+/// The powers bound to the power actions of the character, in slot order.
+#[derive(Component, Debug, Clone, Default, Reflect)]
+#[reflect(Component)]
+pub struct PowerSlots(pub Vec<PowerId>);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, EntityEvent)]
+pub struct ActivatePower {
+    pub entity: Entity,
+    pub power: PowerId,
+}
+
+/// The power was activated, after its cost was paid.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, EntityEvent)]
+pub struct PowerActivated {
+    pub entity: Entity,
+    pub power: PowerId,
+}
+
+fn setup_player_powers(
+    trigger: On<Add, WaltzPlayer>,
+    mut commands: Commands,
+    registry: Res<PowerRegistry>,
+) {
+    let slots = ["Dash", "Blast Jump"]
+        .into_iter()
+        .filter_map(|name| registry.find(name))
+        .collect();
+
+    commands.entity(trigger.entity).insert((
+        Energy::new(100.0, 10.0),
+        PowerCooldowns::default(),
+        PowerSlots(slots),
+    ));
+}
+
+fn activate_power(
+    activate: On<ActivatePower>,
+    mut commands: Commands,
+    registry: Res<PowerRegistry>,
+    mut casters: Query<(&mut PowerCooldowns, Option<&mut Energy>)>,
+) {
+    let ActivatePower { entity, power } = *activate.event();
+
+    let Some((info, power_impl)) = registry.power(power) else {
+        warn!("power {power:?} is not registered");
+        return;
+    };
+
+    let Ok((mut cooldowns, energy)) = casters.get_mut(entity) else {
+        warn!("{entity} can not use powers");
+        return;
+    };
+
+    if cooldowns.remaining(power) > 0.0 {
+        debug!("{} is cooling down", info.name);
+        return;
+    }
+
+    // a caster without energy only pays with the cooldown
+    if let Some(mut energy) = energy {
+        if energy.current < info.cost {
+            debug!("not enough energy for {}", info.name);
+            return;
+        }
+        energy.current -= info.cost;
+    }
+
+    cooldowns.0.insert(power, info.cooldown);
+
+    info!("{entity} activates {}", info.name);
+    commands
+        .entity(entity)
+        .queue(move |caster: EntityWorldMut| power_impl.activate(caster));
+    commands.trigger(PowerActivated { entity, power });
+}
+
+fn tick_power_cooldowns(time: Res<Time>, mut casters: Query<&mut PowerCooldowns>) {
+    let dt = time.delta_secs();
+
+    for mut cooldowns in &mut casters {
+        if cooldowns.0.is_empty() {
+            continue;
+        }
+
+        cooldowns.0.retain(|_, remaining| {
+            *remaining -= dt;
+            *remaining > 0.0
+        });
+    }
+}
+
+fn regenerate_energy(time: Res<Time>, mut casters: Query<&mut Energy>) {
+    let dt = time.delta_secs();
+
+    for mut energy in &mut casters {
+        if energy.current < energy.max {
+            energy.current = (energy.current + energy.regen * dt).min(energy.max);
+        }
+    }
+}
+
+/// Dashes along the facing of the caster, the distance follows the dash distance stat.
+pub struct DashPower {
+    pub distance: f32,
+}
+
+impl Power for DashPower {
+    fn info(&self) -> PowerInfo {
+        PowerInfo {
+            name: "Dash".into(),
+            description: "Dash a short distance forward, also in the air.".into(),
+            icon: None,
+            cooldown: 1.5,
+            cost: 20.0,
+        }
+    }
+
+    fn activate(&self, mut caster: EntityWorldMut) {
+        let distance = caster
+            .get::<Stats>()
+            .and_then(|stats| stats.get(StatKind::DashDistance))
+            .unwrap_or(self.distance);
+        let Some(direction) = caster.get::<Transform>().map(character_facing) else {
+            return;
+        };
+
+        let Some(mut controller) = caster.get_mut::<TnuaController<WaltzTnuaCtrlScheme>>() else {
+            return;
+        };
+
+        controller.action(WaltzTnuaCtrlScheme::Dash(TnuaBuiltinDash {
+            displacement: direction * distance,
+            allow_in_air: true,
+            ..Default::default()
+        }));
+    }
+}
+
+/// Throws the caster up and forward with a knockback.
+pub struct BlastJumpPower {
+    /// the shove in the space of the caster, z is forward
+    pub shove: Vec3,
+}
+
+impl Power for BlastJumpPower {
+    fn info(&self) -> PowerInfo {
+        PowerInfo {
+            name: "Blast Jump".into(),
+            description: "Blast yourself up into the air.".into(),
+            icon: None,
+            cooldown: 4.0,
+            cost: 40.0,
+        }
+    }
+
+    fn activate(&self, mut caster: EntityWorldMut) {
+        let Some(rotation) = caster
+            .get::<Transform>()
+            .map(|transform| transform.rotation)
+        else {
+            return;
+        };
+
+        let Some(mut controller) = caster.get_mut::<TnuaController<WaltzTnuaCtrlScheme>>() else {
+            return;
+        };
+
+        controller.action(WaltzTnuaCtrlScheme::Knockback(TnuaBuiltinKnockback {
+            shove: rotation * self.shove,
+            ..Default::default()
+        }));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Counts its activations on the caster.
+    struct CountPower;
+
+    #[derive(Component, Default)]
+    struct Activations(u32);
+
+    impl Power for CountPower {
+        fn info(&self) -> PowerInfo {
+            PowerInfo {
+                name: "Count".into(),
+                description: "Counts its activations.".into(),
+                icon: None,
+                cooldown: 1.0,
+                cost: 30.0,
+            }
+        }
+
+        fn activate(&self, mut caster: EntityWorldMut) {
+            if let Some(mut activations) = caster.get_mut::<Activations>() {
+                activations.0 += 1;
+            }
+        }
+    }
+
+    fn app() -> App {
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, PowerPlugin))
+            .register_power(CountPower);
+        app
+    }
+
+    fn count_power(app: &App) -> PowerId {
+        app.world()
+            .resource::<PowerRegistry>()
+            .find("Count")
+            .unwrap()
+    }
+
+    fn activate(app: &mut App, entity: Entity, power: PowerId) -> u32 {
+        app.world_mut().trigger(ActivatePower { entity, power });
+        app.world_mut().flush();
+        app.world().get::<Activations>(entity).unwrap().0
+    }
+
+    #[test]
+    fn registered_powers_are_found_by_id() {
+        let app = app();
+        let registry = app.world().resource::<PowerRegistry>();
+
+        let power = registry.find("Count").unwrap();
+        assert_eq!(registry.info(power).unwrap().name, "Count");
+        assert!(
+            registry
+                .iter()
+                .any(|(id, info)| id == power && info.cost == 30.0)
+        );
+        assert_eq!(registry.info(PowerId(registry.powers.len())), None);
+        assert_eq!(registry.find("Missing"), None);
+    }
+
+    #[test]
+    fn activations_spend_energy() {
+        let mut app = app();
+        let power = count_power(&app);
+        let caster = app
+            .world_mut()
+            .spawn((
+                Energy::new(50.0, 0.0),
+                PowerCooldowns::default(),
+                Activations::default(),
+            ))
+            .id();
+
+        assert_eq!(activate(&mut app, caster, power), 1);
+        assert_eq!(app.world().get::<Energy>(caster).unwrap().current, 20.0);
+
+        // the cooldown is over but 20 energy does not pay for another
+        app.world_mut()
+            .get_mut::<PowerCooldowns>(caster)
+            .unwrap()
+            .0
+            .clear();
+        assert_eq!(activate(&mut app, caster, power), 1);
+        assert_eq!(app.world().get::<Energy>(caster).unwrap().current, 20.0);
+    }
+
+    #[test]
+    fn cooling_down_powers_are_rejected() {
+        let mut app = app();
+        let power = count_power(&app);
+        let caster = app
+            .world_mut()
+            .spawn((PowerCooldowns::default(), Activations::default()))
+            .id();
+
+        assert_eq!(activate(&mut app, caster, power), 1);
+        assert_eq!(
+            app.world()
+                .get::<PowerCooldowns>(caster)
+                .unwrap()
+                .remaining(power),
+            1.0
+        );
+        assert_eq!(activate(&mut app, caster, power), 1);
+
+        app.world_mut()
+            .get_mut::<PowerCooldowns>(caster)
+            .unwrap()
+            .0
+            .clear();
+        assert_eq!(activate(&mut app, caster, power), 2);
+    }
 }
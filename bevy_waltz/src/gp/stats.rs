@@ -0,0 +1,352 @@
+//! Character stats modified by the equipped gear.
+//!
+//! Every piece of [`Gear`] carries modifiers for some stats. The final value of a stat is its base
+//! value plus the sum of the additive modifiers, multiplied by every multiplicative modifier, and
+//! is recomputed each time a piece of gear is equipped or unequipped. The final values are copied
+//! into the character configs, so the controller never sees the modifiers themselves.
+use bevy::{platform::collections::HashMap, prelude::*};
+use bevy_tnua::TnuaConfig;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    character::{WaltzTnuaCtrlScheme, WaltzTnuaCtrlSchemeConfig, config::CharacterMotionConfig},
+    gp::damage::Armor,
+};
+
+pub(super) fn plugin(app: &mut App) {
+    app.register_type::<StatKind>()
+        .register_type::<Stats>()
+        .add_observer(equip_gear)
+        .add_observer(unequip_gear)
+        .add_systems(Update, apply_stats);
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Reflect, Serialize, Deserialize)]
+#[reflect(Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StatKind {
+    MoveSpeed,
+    JumpHeight,
+    DashDistance,
+    ClimbSpeed,
+    /// damage absorbed by the character itself, replaces its [`Armor`]
+    Armor,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Reflect, Serialize, Deserialize)]
+#[reflect(Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ModifierOp {
+    Add(f32),
+    Multiply(f32),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Reflect, Serialize, Deserialize)]
+#[reflect(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct StatModifier {
+    pub stat: StatKind,
+    pub op: ModifierOp,
+}
+
+impl StatModifier {
+    pub fn add(stat: StatKind, value: f32) -> Self {
+        Self {
+            stat,
+            op: ModifierOp::Add(value),
+        }
+    }
+
+    pub fn multiply(stat: StatKind, factor: f32) -> Self {
+        Self {
+            stat,
+            op: ModifierOp::Multiply(factor),
+        }
+    }
+}
+
+/// A piece of gear, equipping another piece with the same name replaces it.
+#[derive(Debug, Clone, PartialEq, Reflect, Serialize, Deserialize)]
+#[reflect(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Gear {
+    pub name: String,
+    pub modifiers: Vec<StatModifier>,
+}
+
+/// The stats of a character. Only the stats with a base value are aggregated and applied.
+#[derive(Component, Debug, Clone, Default, Reflect)]
+#[reflect(Component)]
+pub struct Stats {
+    base: HashMap<StatKind, f32>,
+    gear: Vec<Gear>,
+    /// the final values, recomputed each time the gear changes
+    values: HashMap<StatKind, f32>,
+}
+
+impl Stats {
+    pub fn new(base: impl IntoIterator<Item = (StatKind, f32)>) -> Self {
+        let mut stats = Self {
+            base: base.into_iter().collect(),
+            ..Default::default()
+        };
+        stats.aggregate();
+        stats
+    }
+
+    /// The final value of the stat, `None` if it has no base value.
+    pub fn get(&self, stat: StatKind) -> Option<f32> {
+        self.values.get(&stat).copied()
+    }
+
+    pub fn gear(&self) -> &[Gear] {
+        &self.gear
+    }
+
+    /// Equips the gear and returns the piece with the same name it replaces.
+    pub fn equip(&mut self, gear: Gear) -> Option<Gear> {
+        let replaced = self.unequip_without_aggregate(&gear.name);
+        self.gear.push(gear);
+        self.aggregate();
+        replaced
+    }
+
+    pub fn unequip(&mut self, name: &str) -> Option<Gear> {
+        let removed = self.unequip_without_aggregate(name);
+        self.aggregate();
+        removed
+    }
+
+    fn unequip_without_aggregate(&mut self, name: &str) -> Option<Gear> {
+        let index = self.gear.iter().position(|gear| gear.name == name)?;
+        Some(self.gear.remove(index))
+    }
+
+    fn aggregate(&mut self) {
+        self.values = self
+            .base
+            .iter()
+            .map(|(stat, base)| {
+                let modifiers = self
+                    .gear
+                    .iter()
+                    .flat_map(|gear| &gear.modifiers)
+                    .filter(|modifier| modifier.stat == *stat);
+
+                let (sum, product) =
+                    modifiers.fold((0.0, 1.0), |(sum, product), modifier| match modifier.op {
+                        ModifierOp::Add(value) => (sum + value, product),
+                        ModifierOp::Multiply(factor) => (sum, product * factor),
+                    });
+
+                (*stat, ((base + sum) * product).max(0.0))
+            })
+            .collect();
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, EntityEvent)]
+pub struct EquipGear {
+    pub entity: Entity,
+    pub gear: Gear,
+}
+
+#[derive(Debug, Clone, PartialEq, EntityEvent)]
+pub struct UnequipGear {
+    pub entity: Entity,
+    pub name: String,
+}
+
+fn equip_gear(equip: On<EquipGear>, mut stats: Query<&mut Stats>) {
+    let Ok(mut stats) = stats.get_mut(equip.entity) else {
+        warn!("{} has no stats to equip {}", equip.entity, equip.gear.name);
+        return;
+    };
+
+    info!("equip gear {}", equip.gear.name);
+    if let Some(replaced) = stats.equip(equip.gear.clone()) {
+        info!("gear {} was replaced", replaced.name);
+    }
+}
+
+fn unequip_gear(unequip: On<UnequipGear>, mut stats: Query<&mut Stats>) {
+    let Ok(mut stats) = stats.get_mut(unequip.entity) else {
+        return;
+    };
+
+    if stats.unequip(&unequip.name).is_some() {
+        info!("unequip gear {}", unequip.name);
+    }
+}
+
+/// Copies the final stat values into the configs of the character.
+fn apply_stats(
+    mut commands: Commands,
+    mut characters: Query<
+        (
+            Entity,
+            &Stats,
+            Option<&mut CharacterMotionConfig>,
+            Option<&TnuaConfig<WaltzTnuaCtrlScheme>>,
+        ),
+        Changed<Stats>,
+    >,
+    mut scheme_configs: ResMut<Assets<WaltzTnuaCtrlSchemeConfig>>,
+) {
+    for (entity, stats, motion_config, scheme_config) in &mut characters {
+        if let Some(mut motion_config) = motion_config {
+            if let Some(speed) = stats.get(StatKind::MoveSpeed) {
+                motion_config.speed = speed;
+            }
+            if let Some(climb_speed) = stats.get(StatKind::ClimbSpeed) {
+                motion_config.climb_speed = climb_speed;
+            }
+            if let Some(dash_distance) = stats.get(StatKind::DashDistance) {
+                motion_config.dash_distance = dash_distance;
+            }
+        }
+
+        // every character owns its scheme config, see `setup_character_with_entity_cmd`
+        if let Some(mut scheme_config) =
+            scheme_config.and_then(|config| scheme_configs.get_mut(&config.0))
+        {
+            if let Some(height) = stats.get(StatKind::JumpHeight) {
+                scheme_config.jump.height = height;
+            }
+            if let Some(dash_distance) = stats.get(StatKind::DashDistance) {
+                scheme_config.dash.horizontal_distance = dash_distance;
+            }
+            if let Some(climb_speed) = stats.get(StatKind::ClimbSpeed) {
+                scheme_config.climb.climb_speed = climb_speed;
+            }
+        }
+
+        if let Some(armor) = stats.get(StatKind::Armor) {
+            commands.entity(entity).insert(Armor::new(armor));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn boots() -> Gear {
+        Gear {
+            name: "boots".into(),
+            modifiers: vec![
+                StatModifier::add(StatKind::MoveSpeed, 2.0),
+                StatModifier::multiply(StatKind::JumpHeight, 1.2),
+            ],
+        }
+    }
+
+    fn cape() -> Gear {
+        Gear {
+            name: "cape".into(),
+            modifiers: vec![
+                StatModifier::multiply(StatKind::MoveSpeed, 1.5),
+                StatModifier::add(StatKind::MoveSpeed, 1.0),
+            ],
+        }
+    }
+
+    fn base() -> Stats {
+        Stats::new([(StatKind::MoveSpeed, 10.0), (StatKind::JumpHeight, 4.0)])
+    }
+
+    #[test]
+    fn modifiers_add_before_they_multiply() {
+        let mut stats = base();
+        assert_eq!(stats.equip(boots()), None);
+        assert_eq!(stats.equip(cape()), None);
+
+        // (10 + 2 + 1) * 1.5, whatever the order of the gear and of its modifiers
+        assert_eq!(stats.get(StatKind::MoveSpeed), Some(19.5));
+        assert_eq!(stats.get(StatKind::JumpHeight), Some(4.0 * 1.2));
+
+        let mut reversed = base();
+        reversed.equip(cape());
+        reversed.equip(boots());
+        assert_eq!(reversed.get(StatKind::MoveSpeed), Some(19.5));
+    }
+
+    #[test]
+    fn unequipping_restores_the_stats() {
+        let mut stats = base();
+        stats.equip(boots());
+        stats.equip(cape());
+
+        assert_eq!(stats.unequip("boots"), Some(boots()));
+        assert_eq!(stats.get(StatKind::MoveSpeed), Some((10.0 + 1.0) * 1.5));
+        assert_eq!(stats.get(StatKind::JumpHeight), Some(4.0));
+
+        assert_eq!(stats.unequip("cape"), Some(cape()));
+        assert_eq!(stats.get(StatKind::MoveSpeed), Some(10.0));
+        assert!(stats.gear().is_empty());
+
+        assert_eq!(stats.unequip("cape"), None);
+    }
+
+    #[test]
+    fn gear_with_the_same_name_is_replaced() {
+        let mut stats = base();
+        stats.equip(boots());
+
+        let better_boots = Gear {
+            modifiers: vec![StatModifier::add(StatKind::MoveSpeed, 5.0)],
+            ..boots()
+        };
+        assert_eq!(stats.equip(better_boots), Some(boots()));
+        assert_eq!(stats.gear().len(), 1);
+        assert_eq!(stats.get(StatKind::MoveSpeed), Some(15.0));
+    }
+
+    #[test]
+    fn only_stats_with_a_base_value_are_aggregated() {
+        let mut stats = base();
+        stats.equip(Gear {
+            name: "cursed ring".into(),
+            modifiers: vec![
+                StatModifier::add(StatKind::Armor, 5.0),
+                StatModifier::add(StatKind::MoveSpeed, -20.0),
+            ],
+        });
+
+        assert_eq!(stats.get(StatKind::Armor), None);
+        // a stat never drops below zero
+        assert_eq!(stats.get(StatKind::MoveSpeed), Some(0.0));
+    }
+
+    #[test]
+    fn gear_events_update_the_stats() {
+        let mut world = World::new();
+        world.add_observer(equip_gear);
+        world.add_observer(unequip_gear);
+        let entity = world.spawn(base()).id();
+
+        world.trigger(EquipGear {
+            entity,
+            gear: boots(),
+        });
+        world.trigger(EquipGear {
+            entity,
+            gear: cape(),
+        });
+        world.flush();
+        assert_eq!(
+            world.get::<Stats>(entity).unwrap().get(StatKind::MoveSpeed),
+            Some(19.5)
+        );
+
+        world.trigger(UnequipGear {
+            entity,
+            name: "cape".into(),
+        });
+        world.flush();
+        assert_eq!(
+            world.get::<Stats>(entity).unwrap().get(StatKind::MoveSpeed),
+            Some(12.0)
+        );
+    }
+}
@@ -0,0 +1,33 @@
+//! Bumpers knock back the characters walking into them, hard enough to throw the player into its
+//! ragdoll, see the `knockback_speed` of the animation table.
+use avian3d::prelude::CollisionStart;
+use bevy::prelude::*;
+use bevy_tnua::{builtins::TnuaBuiltinKnockback, math::AdjustPrecision, prelude::TnuaController};
+
+use crate::character::WaltzTnuaCtrlScheme;
+
+pub(super) fn plugin(app: &mut App) {
+    app.add_observer(bump);
+}
+
+/// A sensor volume that knocks back the characters entering it by the shove, in world space.
+#[derive(Component, Debug, Clone, Copy)]
+pub struct Bumper(pub Vec3);
+
+fn bump(
+    start: On<CollisionStart>,
+    bumpers: Query<&Bumper>,
+    mut characters: Query<&mut TnuaController<WaltzTnuaCtrlScheme>>,
+) {
+    let Ok(Bumper(shove)) = bumpers.get(start.collider1) else {
+        return;
+    };
+    let Some(mut controller) = start.body2.and_then(|body| characters.get_mut(body).ok()) else {
+        return;
+    };
+
+    controller.action(WaltzTnuaCtrlScheme::Knockback(TnuaBuiltinKnockback {
+        shove: shove.adjust_precision(),
+        ..Default::default()
+    }));
+}
@@ -0,0 +1,412 @@
+//! Levels loaded from a glTF file, like the ones exported from Blender.
+//!
+//! The custom properties of the Blender objects are exported as the extras of their glTF node.
+//! Every property is read like a component of the node, its value is written in RON the way the
+//! Blenvy exporter writes it, and `()` marks a node without a value:
+//!
+//! - `Collider`: the meshes of the node are static colliders, shaped as `Trimesh` by default,
+//!   `ConvexHull` or `ConvexDecomposition`. The node name suffixes `-trimesh`, `-hull` and
+//!   `-convex` do the same without a property.
+//! - `Surface`: the [`Surface`] of the colliders, like `Grass`.
+//! - `Climable`: the meshes are [`Climable`], a sensor without a `Collider` property.
+//! - `Checkpoint`: the meshes are a hidden [`Checkpoint`](super::Checkpoint) volume, the value
+//!   names its spawn point, the node name without one.
+//! - `OutOfBounds`: the meshes are a hidden [`OutOfBounds`](super::OutOfBounds) volume.
+//! - `SpawnPoint`: a spawn point where the node is, named by the value or the node name. `Player`
+//!   marks the level start.
+//! - `KillPlane`: a kill plane at the height of the value, or of the node without one.
+//! - `Shadows`: the light of the node casts shadows.
+//!
+//! The other properties are left alone. The level counts as loading until its scene is spawned
+//! and read, the player is placed once it is done.
+use avian3d::prelude::{ColliderConstructor, RigidBody};
+use bevy::{gltf::GltfExtras, prelude::*};
+use serde::{Deserialize, de::DeserializeOwned};
+
+use super::{
+    Climable, LEVEL_START, LevelLoading,
+    helper::{LevelSetupHelper, LevelSetupHelperEntityCommandsExtension, static_layers},
+    respawn,
+};
+use crate::character::Surface;
+
+pub(super) fn plugin(app: &mut App) {
+    app.add_systems(Update, read_gltf_levels.before(respawn::respawn_player));
+}
+
+/// The root of a level spawned from a glTF scene.
+#[derive(Component, Debug)]
+pub struct GltfLevel;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+enum ColliderShape {
+    #[default]
+    Trimesh,
+    ConvexHull,
+    ConvexDecomposition,
+}
+
+impl ColliderShape {
+    /// The shape named by the suffix of a node name, after the `.001` Blender adds to copies.
+    fn from_name(name: &str) -> Option<Self> {
+        let name = match name.rsplit_once('.') {
+            Some((base, copy)) if copy.chars().all(|c| c.is_ascii_digit()) => base,
+            _ => name,
+        };
+
+        [
+            ("-trimesh", ColliderShape::Trimesh),
+            ("-hull", ColliderShape::ConvexHull),
+            ("-convex", ColliderShape::ConvexDecomposition),
+        ]
+        .into_iter()
+        .find_map(|(suffix, shape)| name.ends_with(suffix).then_some(shape))
+    }
+}
+
+impl From<ColliderShape> for ColliderConstructor {
+    fn from(shape: ColliderShape) -> Self {
+        match shape {
+            ColliderShape::Trimesh => ColliderConstructor::TrimeshFromMesh,
+            ColliderShape::ConvexHull => ColliderConstructor::ConvexHullFromMesh,
+            ColliderShape::ConvexDecomposition => ColliderConstructor::ConvexDecompositionFromMesh,
+        }
+    }
+}
+
+/// Blenvy writes the values of newtypes in parentheses, `("start")`.
+#[derive(Deserialize)]
+struct Newtype<T>(T);
+
+/// Reads a property value, a newtype or the bare value, `()` reads as `None`.
+fn read_value<T: DeserializeOwned>(value: &str) -> Result<Option<T>, ron::error::SpannedError> {
+    if value.trim() == "()" {
+        return Ok(None);
+    }
+
+    ron::from_str::<Newtype<T>>(value)
+        .map(|Newtype(value)| value)
+        .or_else(|_| ron::from_str(value))
+        .map(Some)
+}
+
+/// The properties of a node this module knows about.
+#[derive(Debug, Default)]
+struct NodeProperties {
+    collider: Option<ColliderShape>,
+    surface: Option<Surface>,
+    climable: bool,
+    checkpoint: Option<String>,
+    out_of_bounds: bool,
+    spawn_point: Option<String>,
+    /// the height of the kill plane, `Some(None)` for the height of the node
+    kill_plane: Option<Option<f32>>,
+    shadows: bool,
+}
+
+impl NodeProperties {
+    /// Reads the properties from the name suffix and the JSON extras of the node, a property
+    /// with a bad value is reported and left out.
+    fn read(name: &str, extras: Option<&str>) -> Self {
+        let mut properties = NodeProperties {
+            collider: ColliderShape::from_name(name),
+            ..Default::default()
+        };
+        // Blender only exports the extras of the objects with custom properties
+        let Some(extras) = extras else {
+            return properties;
+        };
+
+        let extras =
+            match serde_json::from_str::<serde_json::Map<String, serde_json::Value>>(extras) {
+                Ok(extras) => extras,
+                Err(err) => {
+                    error!("the extras of level node {name} are not a JSON object: {err}");
+                    return properties;
+                }
+            };
+
+        for (key, value) in extras {
+            // Blender writes the properties of Blenvy as strings, its own as numbers or strings
+            let value = match value {
+                serde_json::Value::String(value) => value,
+                value => value.to_string(),
+            };
+
+            let result = match key.as_str() {
+                "Collider" => read_value(&value)
+                    .map(|shape| properties.collider = Some(shape.unwrap_or_default())),
+                "Surface" => read_value(&value).map(|surface| properties.surface = surface),
+                "Climable" => {
+                    properties.climable = true;
+                    Ok(())
+                }
+                "Checkpoint" => read_value(&value).map(|spawn_point: Option<String>| {
+                    properties.checkpoint = Some(spawn_point.unwrap_or_else(|| name.to_string()))
+                }),
+                "OutOfBounds" => {
+                    properties.out_of_bounds = true;
+                    Ok(())
+                }
+                "SpawnPoint" => read_value(&value).map(|spawn_point: Option<String>| {
+                    properties.spawn_point = Some(spawn_point.unwrap_or_else(|| name.to_string()))
+                }),
+                "Player" => {
+                    properties.spawn_point = Some(LEVEL_START.to_string());
+                    Ok(())
+                }
+                "KillPlane" => {
+                    read_value(&value).map(|height| properties.kill_plane = Some(height))
+                }
+                "Shadows" => {
+                    properties.shadows = true;
+                    Ok(())
+                }
+                _ => Ok(()),
+            };
+
+            if let Err(err) = result {
+                error!("level node {name} has a bad {key} property {value:?}: {err}");
+            }
+        }
+
+        properties
+    }
+
+    /// The meshes of the node become volumes the characters pass through.
+    fn is_volume(&self) -> bool {
+        self.checkpoint.is_some()
+            || self.out_of_bounds
+            || (self.climable && self.collider.is_none())
+    }
+}
+
+/// Reads the nodes of the glTF levels once their scene is spawned, which happens frames after
+/// the level is set up.
+fn read_gltf_levels(
+    mut helper: LevelSetupHelper,
+    levels: Query<Entity, (With<GltfLevel>, With<LevelLoading>)>,
+    children: Query<&Children>,
+    nodes: Query<(Option<&GltfExtras>, Option<&Name>, &GlobalTransform)>,
+    meshes: Query<(), With<Mesh3d>>,
+    mut lights: Query<(
+        Option<&mut PointLight>,
+        Option<&mut SpotLight>,
+        Option<&mut DirectionalLight>,
+    )>,
+) {
+    for level in &levels {
+        // the scene is not spawned yet
+        if !children.contains(level) {
+            continue;
+        }
+
+        for node in children.iter_descendants(level) {
+            let Ok((extras, name, transform)) = nodes.get(node) else {
+                continue;
+            };
+            // the primitives are read with their node, their name repeats the one of the mesh
+            if extras.is_none() && meshes.contains(node) {
+                continue;
+            }
+            let name = name.map(Name::as_str).unwrap_or_default();
+            let properties = NodeProperties::read(name, extras.map(|extras| extras.value.as_str()));
+
+            // the meshes of a node are its primitives, spawned as its children
+            let mut primitives = std::iter::once(node)
+                .chain(children.get(node).into_iter().flatten().copied())
+                .filter(|entity| meshes.contains(*entity))
+                .collect::<Vec<_>>();
+            let volume = properties.is_volume();
+            if properties.collider.is_none() && !volume {
+                primitives.clear();
+            } else if primitives.is_empty() {
+                warn!("level node {name} has collider properties but no mesh");
+            }
+
+            for primitive in primitives {
+                let mut cmd = helper.entity(primitive);
+                let shape = properties.collider.unwrap_or(ColliderShape::ConvexHull);
+                cmd.insert(ColliderConstructor::from(shape));
+                if volume {
+                    cmd.make_sensor();
+                } else {
+                    cmd.insert((RigidBody::Static, static_layers()));
+                }
+
+                if let Some(surface) = properties.surface {
+                    cmd.insert(surface);
+                }
+                if properties.climable {
+                    cmd.insert(Climable);
+                }
+                if let Some(spawn_point) = &properties.checkpoint {
+                    cmd.make_checkpoint(spawn_point).insert(Visibility::Hidden);
+                } else if properties.out_of_bounds {
+                    cmd.make_out_of_bounds().insert(Visibility::Hidden);
+                }
+            }
+
+            // spawn points and kill planes live at the root of the level
+            let transform = transform.compute_transform();
+            if let Some(spawn_point) = properties.spawn_point {
+                helper.spawn_spawn_point(spawn_point, transform);
+            }
+            if let Some(height) = properties.kill_plane {
+                helper.spawn_kill_plane(height.unwrap_or(transform.translation.y));
+            }
+
+            if properties.shadows {
+                let light_entities =
+                    std::iter::once(node).chain(children.get(node).into_iter().flatten().copied());
+                for light in light_entities {
+                    let Ok((point, spot, directional)) = lights.get_mut(light) else {
+                        continue;
+                    };
+                    if let Some(mut point) = point {
+                        point.shadow_maps_enabled = true;
+                    }
+                    if let Some(mut spot) = spot {
+                        spot.shadow_maps_enabled = true;
+                    }
+                    if let Some(mut directional) = directional {
+                        directional.shadow_maps_enabled = true;
+                    }
+                }
+            }
+        }
+
+        info!("glTF level {level} is read");
+        helper.entity(level).remove::<LevelLoading>();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn name_suffixes_pick_the_collider_shape() {
+        assert_eq!(
+            ColliderShape::from_name("wall-trimesh"),
+            Some(ColliderShape::Trimesh)
+        );
+        assert_eq!(
+            ColliderShape::from_name("rock-hull"),
+            Some(ColliderShape::ConvexHull)
+        );
+        assert_eq!(
+            ColliderShape::from_name("crate-convex"),
+            Some(ColliderShape::ConvexDecomposition)
+        );
+        assert_eq!(ColliderShape::from_name("wall"), None);
+        assert_eq!(ColliderShape::from_name(""), None);
+    }
+
+    #[test]
+    fn blender_copy_numbers_are_ignored() {
+        assert_eq!(
+            ColliderShape::from_name("rock-hull.001"),
+            Some(ColliderShape::ConvexHull)
+        );
+        assert_eq!(
+            ColliderShape::from_name("crate-convex.12"),
+            Some(ColliderShape::ConvexDecomposition)
+        );
+        assert_eq!(ColliderShape::from_name("cube.001"), None);
+        // only a number after the last dot is a copy
+        assert_eq!(
+            ColliderShape::from_name("v1.2-hull"),
+            Some(ColliderShape::ConvexHull)
+        );
+        assert_eq!(ColliderShape::from_name("rock-hull.old"), None);
+    }
+
+    #[test]
+    fn values_are_newtypes_bare_or_empty() {
+        assert_eq!(
+            read_value::<String>(r#"("start")"#).unwrap(),
+            Some("start".to_string())
+        );
+        assert_eq!(
+            read_value::<String>(r#""start""#).unwrap(),
+            Some("start".to_string())
+        );
+        assert_eq!(read_value::<f32>("-10.5").unwrap(), Some(-10.5));
+        assert_eq!(read_value::<f32>("(-10.5)").unwrap(), Some(-10.5));
+        assert_eq!(read_value::<Surface>(" () ").unwrap(), None);
+        assert!(read_value::<Surface>("(Lava)").is_err());
+    }
+
+    #[test]
+    fn suffixed_nodes_without_extras_get_a_collider() {
+        let properties = NodeProperties::read("rock-hull.001", None);
+        assert_eq!(properties.collider, Some(ColliderShape::ConvexHull));
+        assert!(!properties.is_volume());
+
+        let properties = NodeProperties::read("rock", None);
+        assert_eq!(properties.collider, None);
+    }
+
+    #[test]
+    fn blenvy_properties_are_read() {
+        let properties = NodeProperties::read(
+            "ramp",
+            Some(
+                r#"{
+                    "Collider": "(ConvexDecomposition)",
+                    "Surface": "Grass",
+                    "SpawnPoint": "(\"ramp top\")",
+                    "Shadows": "()",
+                    "Unrelated": "(1, 2)"
+                }"#,
+            ),
+        );
+        assert_eq!(
+            properties.collider,
+            Some(ColliderShape::ConvexDecomposition)
+        );
+        assert_eq!(properties.surface, Some(Surface::Grass));
+        assert_eq!(properties.spawn_point.as_deref(), Some("ramp top"));
+        assert!(properties.shadows);
+        assert!(!properties.is_volume());
+    }
+
+    #[test]
+    fn empty_values_fall_back_to_the_node() {
+        let properties = NodeProperties::read(
+            "gate-hull",
+            Some(r#"{"Collider": "()", "Checkpoint": "()", "KillPlane": "()"}"#),
+        );
+        // the property takes precedence over the name suffix
+        assert_eq!(properties.collider, Some(ColliderShape::Trimesh));
+        assert_eq!(properties.checkpoint.as_deref(), Some("gate-hull"));
+        assert_eq!(properties.kill_plane, Some(None));
+        assert!(properties.is_volume());
+    }
+
+    #[test]
+    fn numeric_extras_are_read() {
+        // Blender writes its own float properties as JSON numbers
+        let properties = NodeProperties::read("floor", Some(r#"{"KillPlane": -10.5}"#));
+        assert_eq!(properties.kill_plane, Some(Some(-10.5)));
+    }
+
+    #[test]
+    fn bad_properties_are_left_out() {
+        let properties = NodeProperties::read(
+            "wall-hull",
+            Some(r#"{"Surface": "(Lava)", "Climable": "()", "Player": "()"}"#),
+        );
+        assert_eq!(properties.surface, None);
+        assert!(properties.climable);
+        assert_eq!(properties.spawn_point.as_deref(), Some(LEVEL_START));
+        // a climbable node with a collider is solid
+        assert!(!properties.is_volume());
+
+        // extras that are not an object keep the name suffix
+        let properties = NodeProperties::read("wall-hull", Some("[1, 2]"));
+        assert_eq!(properties.collider, Some(ColliderShape::ConvexHull));
+        assert!(!properties.climable);
+    }
+}
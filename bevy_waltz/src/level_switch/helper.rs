@@ -1,8 +1,26 @@
-use avian3d::prelude::{AngularVelocity, Collider, LinearVelocity, RigidBody, Sensor};
+use avian3d::prelude::{
+    AngularVelocity, Collider, CollisionEventsEnabled, CollisionLayers, LayerMask, LinearVelocity,
+    RigidBody, Sensor,
+};
 use bevy::{ecs::system::SystemParam, prelude::*};
-use bevy_tnua::math::{AsF32, Float, Quaternion, Vector3};
-
-use super::LevelObject;
+use bevy_tnua::{
+    TnuaGhostPlatform,
+    math::{AsF32, Float, Quaternion, Vector3},
+};
+
+use super::{
+    Bumper, Checkpoint, KillPlane, LevelLoading, LevelObject, OutOfBounds, SpawnPoint,
+    gltf_level::GltfLevel,
+};
+use crate::camera::config::CollisionLayer;
+
+/// Static level geometry collides with everything and blocks the camera.
+pub(super) fn static_layers() -> CollisionLayers {
+    CollisionLayers::new(
+        [CollisionLayer::Terrain, CollisionLayer::CameraObstacle],
+        LayerMask::ALL,
+    )
+}
 
 #[derive(SystemParam, Deref, DerefMut)]
 pub struct LevelSetupHelper<'w, 's> {
@@ -24,6 +42,43 @@ impl<'w, 's> LevelSetupHelper<'w, 's> {
             .spawn((LevelObject, Name::new(name.to_string())))
     }
 
+    /// Spawns a spawn point, the player spawns at the one named [`LEVEL_START`](super::LEVEL_START)
+    /// when the level loads.
+    pub fn spawn_spawn_point(
+        &mut self,
+        name: impl ToString,
+        transform: Transform,
+    ) -> EntityCommands<'_> {
+        let name = name.to_string();
+        let mut cmd = self.spawn_named(format!("spawn point {name}"));
+        cmd.insert((SpawnPoint(name), transform));
+        cmd
+    }
+
+    /// Spawns a kill plane, the player respawns once it falls below `height`.
+    pub fn spawn_kill_plane(&mut self, height: f32) -> EntityCommands<'_> {
+        let mut cmd = self.spawn_named("kill plane");
+        cmd.insert(KillPlane(height));
+        cmd
+    }
+
+    /// Spawns the first scene of a glTF file as the level, its nodes are read once it is spawned,
+    /// see [`gltf_level`](super::gltf_level).
+    pub fn spawn_gltf_level(&mut self, path: impl ToString) -> EntityCommands<'_> {
+        let path = path.to_string();
+        let scene = self
+            .asset_server
+            .load(GltfAssetLabel::Scene(0).from_asset(path.clone()));
+        let mut cmd = self.spawn_named(path);
+        cmd.insert((
+            WorldAssetRoot(scene),
+            Transform::default(),
+            GltfLevel,
+            LevelLoading,
+        ));
+        cmd
+    }
+
     pub fn spawn_floor(&mut self, color: impl Into<Color>) -> EntityCommands<'_> {
         let mesh = self
             .meshes
@@ -32,7 +87,7 @@ impl<'w, 's> LevelSetupHelper<'w, 's> {
         let mut command = self.spawn_named("Floor");
         command.insert((Mesh3d(mesh), MeshMaterial3d(material)));
 
-        command.insert(RigidBody::Static);
+        command.insert((RigidBody::Static, static_layers()));
         command.insert(Collider::half_space(Vector3::Y));
         // command.insert(Collider::cuboid(128.0, 0.01, 128.0));
         command
@@ -68,7 +123,7 @@ impl<'w, 's> LevelSetupHelper<'w, 's> {
 
         cmd.insert((WorldAssetRoot(scene), transform));
 
-        cmd.insert(RigidBody::Static);
+        cmd.insert((RigidBody::Static, static_layers()));
         cmd.insert(Collider::cuboid(size.x, size.y, size.z));
 
         cmd
@@ -101,7 +156,11 @@ impl LevelSetupHelperWithMaterial<'_, '_, '_> {
         let mut cmd =
             self.spawn_mesh_without_physics(name, transform, Cuboid::from_size(size.f32()));
 
-        cmd.insert((RigidBody::Static, Collider::cuboid(size.x, size.y, size.z)));
+        cmd.insert((
+            RigidBody::Static,
+            static_layers(),
+            Collider::cuboid(size.x, size.y, size.z),
+        ));
 
         cmd
     }
@@ -136,6 +195,7 @@ impl LevelSetupHelperWithMaterial<'_, '_, '_> {
 
         cmd.insert((
             RigidBody::Static,
+            static_layers(),
             Collider::compound(
                 parts
                     .iter()
@@ -165,6 +225,7 @@ impl LevelSetupHelperWithMaterial<'_, '_, '_> {
 
         cmd.insert((
             RigidBody::Static,
+            static_layers(),
             Collider::cylinder(radius, 2.0 * half_height),
         ));
 
@@ -197,6 +258,10 @@ pub trait LevelSetupHelperEntityCommandsExtension {
     fn make_kinematic_with_angular_velocity(&mut self, angvel: Vector3) -> &mut Self;
     fn add_ball_collider(&mut self, radius: Float) -> &mut Self;
     fn make_sensor(&mut self) -> &mut Self;
+    fn make_one_way(&mut self) -> &mut Self;
+    fn make_checkpoint(&mut self, spawn_point: impl ToString) -> &mut Self;
+    fn make_out_of_bounds(&mut self) -> &mut Self;
+    fn make_bumper(&mut self, shove: Vector3) -> &mut Self;
 }
 
 impl LevelSetupHelperEntityCommandsExtension for EntityCommands<'_> {
@@ -217,6 +282,34 @@ impl LevelSetupHelperEntityCommandsExtension for EntityCommands<'_> {
     }
 
     fn make_sensor(&mut self) -> &mut Self {
-        self.insert(Sensor)
+        // sensors must not block the camera
+        self.insert((
+            Sensor,
+            CollisionLayers::new(CollisionLayer::Sensor, LayerMask::ALL),
+        ))
+    }
+
+    fn make_one_way(&mut self) -> &mut Self {
+        // the characters never collide with the platform, their control system decides
+        // whether to stand on it from what the ghost sensor sees
+        self.insert((
+            TnuaGhostPlatform,
+            CollisionLayers::new(CollisionLayer::FallThrough, CollisionLayer::FallThrough),
+        ))
+    }
+
+    fn make_checkpoint(&mut self, spawn_point: impl ToString) -> &mut Self {
+        self.make_sensor()
+            .insert((Checkpoint(spawn_point.to_string()), CollisionEventsEnabled))
+    }
+
+    fn make_out_of_bounds(&mut self) -> &mut Self {
+        self.make_sensor()
+            .insert((OutOfBounds, CollisionEventsEnabled))
+    }
+
+    fn make_bumper(&mut self, shove: Vector3) -> &mut Self {
+        self.make_sensor()
+            .insert((Bumper(shove.f32()), CollisionEventsEnabled))
     }
 }
@@ -1,12 +1,20 @@
+use crate::{
+    character::{Surface, config::FallingThroughControlScheme},
+    gp::{Armor, Health, RespawnAfter},
+    lock_on::Targetable,
+};
+
 use super::{
-    Climable, PositionPlayer,
+    Climable, LEVEL_START,
     helper::{LevelSetupHelper, LevelSetupHelperEntityCommandsExtension},
 };
+use avian3d::prelude::Collider;
 use bevy::{color::palettes::css, prelude::*};
 use bevy_tnua::math::Vector3;
 
 pub fn setup_level(mut helper: LevelSetupHelper) {
-    helper.spawn(PositionPlayer::from(Vec3::new(0.0, 10.0, 0.0)));
+    helper.spawn_spawn_point(LEVEL_START, Transform::from_xyz(0.0, 10.0, 0.0));
+    helper.spawn_kill_plane(-20.0);
 
     helper.spawn((PointLight::default(), Transform::from_xyz(5.0, 5.0, 5.0)));
     helper.spawn((
@@ -18,7 +26,7 @@ pub fn setup_level(mut helper: LevelSetupHelper) {
         Transform::default().looking_at(-Vec3::Y, Vec3::Z),
     ));
 
-    helper.spawn_floor(css::WHITE);
+    helper.spawn_floor(css::WHITE).insert(Surface::Grass);
 
     let mut obstacles_helper = helper.with_color(css::GRAY);
 
@@ -40,6 +48,18 @@ pub fn setup_level(mut helper: LevelSetupHelper) {
         Vector3::new(4.0, 0.5, 4.0),
     );
 
+    // standing on the floating floor respawns the player up there
+    helper.spawn_spawn_point("floating floor", Transform::from_xyz(10.0, 10.0, 0.0));
+    helper
+        .with_color(Color::from(css::LIME).with_alpha(0.3))
+        .spawn_mesh_without_physics(
+            "floating floor checkpoint",
+            Transform::from_xyz(10.0, 10.5, 0.0),
+            Cuboid::new(2.0, 2.0, 2.0),
+        )
+        .insert(Collider::cuboid(2.0, 2.0, 2.0))
+        .make_checkpoint("floating floor");
+
     helper
         .with_color(css::SKY_BLUE)
         .spawn_cylinder("vine", Transform::from_xyz(5.0, 1.0, 5.0), 0.1, 10.0)
@@ -56,4 +76,81 @@ pub fn setup_level(mut helper: LevelSetupHelper) {
         )
         .make_sensor()
         .insert(Climable);
+
+    // throws the player into its ragdoll, far harder than the blast jump power
+    helper
+        .with_color(css::DEEP_PINK)
+        .spawn_mesh_without_physics(
+            "bumper",
+            Transform::from_xyz(12.0, 0.25, 10.0),
+            Cuboid::new(3.0, 0.5, 3.0),
+        )
+        .insert(Collider::cuboid(3.0, 0.5, 3.0))
+        .make_bumper(Vector3::new(0.0, 16.0, -12.0));
+
+    // jump up through the stacks of one-way platforms, crouch to fall back down, every stack is
+    // fallen through with another scheme
+    for (scheme, color, x, z) in [
+        (
+            FallingThroughControlScheme::JumpThroughOnly,
+            css::LIGHT_SALMON,
+            -12.0,
+            8.0,
+        ),
+        (
+            FallingThroughControlScheme::WithoutHelper,
+            css::SALMON,
+            -19.0,
+            8.0,
+        ),
+        (
+            FallingThroughControlScheme::SingleFall,
+            css::DARK_SALMON,
+            -12.0,
+            15.0,
+        ),
+        (
+            FallingThroughControlScheme::KeepFalling,
+            css::LIGHT_CORAL,
+            -19.0,
+            15.0,
+        ),
+    ] {
+        let mut one_way_helper = helper.with_color(color);
+        for (index, y) in [3.0, 6.0, 9.0].into_iter().enumerate() {
+            one_way_helper
+                .spawn_cuboid(
+                    format!("{scheme:?} one-way platform {index}"),
+                    Transform::from_xyz(x, y, z),
+                    Vector3::new(6.0, 0.5, 6.0),
+                )
+                .make_one_way()
+                .insert((Surface::Metal, scheme));
+        }
+    }
+
+    let mut targets_helper = helper.with_color(css::ORANGE_RED);
+    let mut dummies = Vec::new();
+    for (index, x) in [-6.0, 0.0, 6.0].into_iter().enumerate() {
+        let dummy = targets_helper
+            .spawn_cylinder(
+                format!("target dummy {index}"),
+                Transform::from_xyz(x, 1.0, -12.0),
+                0.4,
+                1.0,
+            )
+            .insert((Targetable::default(), Health::new(50.0), RespawnAfter(3.0)))
+            .id();
+        dummies.push(dummy);
+    }
+
+    // shots on the helmet are absorbed by it before they reach the dummy
+    helper
+        .with_color(css::DARK_SLATE_GRAY)
+        .spawn_cuboid(
+            "dummy helmet",
+            Transform::from_xyz(0.0, 1.2, 0.0),
+            Vector3::new(0.9, 0.4, 0.9),
+        )
+        .insert((ChildOf(dummies[1]), Armor::new(8.0)));
 }
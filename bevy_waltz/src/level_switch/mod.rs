@@ -1,20 +1,20 @@
-use std::time::Duration;
+use bevy::{ecs::system::SystemId, prelude::*};
 
-use avian3d::prelude::{AngularVelocity, LinearVelocity};
-use bevy::{
-    ecs::{query::QueryData, system::SystemId},
-    prelude::*,
-};
-
-use crate::WaltzPlayer;
+use helper::LevelSetupHelper;
 
 pub struct LevelSwitchPlugin {
     levels: Vec<(String, Box<dyn Send + Sync + Fn(&mut World) -> SystemId>)>,
     default_level: Option<String>,
 }
 
+mod bumper;
+mod gltf_level;
 mod helper;
 pub mod jungle_gym;
+mod respawn;
+
+pub use bumper::Bumper;
+pub use respawn::{Checkpoint, KillPlane, LEVEL_START, OutOfBounds, PlayerRespawn, SpawnPoint};
 
 #[derive(Component)]
 pub struct Climable;
@@ -27,6 +27,15 @@ impl LevelSwitchPlugin {
         }
     }
 
+    /// Registers a level spawned from the first scene of a glTF file, its nodes are read for
+    /// colliders, volumes, spawn points and lights.
+    pub fn with_gltf(self, name: impl ToString, path: impl ToString) -> Self {
+        let path = path.to_string();
+        self.with(name, move |mut helper: LevelSetupHelper| {
+            helper.spawn_gltf_level(&path);
+        })
+    }
+
     pub fn with<M>(
         mut self,
         name: impl ToString,
@@ -63,7 +72,8 @@ impl Plugin for LevelSwitchPlugin {
 
         app.insert_resource(SwitchableLevels { current: 0, levels });
         app.add_message::<SwitchToLevel>();
-        app.add_systems(Update, (handle_level_switch, handle_player_position));
+        app.add_plugins((respawn::plugin, gltf_level::plugin, bumper::plugin));
+        app.add_systems(Update, handle_level_switch.before(respawn::respawn_player));
         app.add_systems(Startup, move |mut writer: MessageWriter<SwitchToLevel>| {
             writer.write(SwitchToLevel(level_index));
         });
@@ -104,26 +114,16 @@ impl SwitchableLevels {
 #[derive(Component)]
 pub struct LevelObject;
 
+/// Marks a level object still loading, the player is placed once no level object is.
 #[derive(Component)]
-pub struct PositionPlayer {
-    position: Vec3,
-    ttl: Timer,
-}
-
-impl From<Vec3> for PositionPlayer {
-    fn from(position: Vec3) -> Self {
-        Self {
-            position,
-            ttl: Timer::new(Duration::from_millis(500), TimerMode::Once),
-        }
-    }
-}
+pub struct LevelLoading;
 
 // Observer maybe suitable for this function
 fn handle_level_switch(
     mut reader: MessageReader<SwitchToLevel>,
     mut switchable_levels: ResMut<SwitchableLevels>,
-    query: Query<Entity, Or<(With<LevelObject>, With<PositionPlayer>)>>,
+    mut player_respawn: ResMut<PlayerRespawn>,
+    query: Query<Entity, With<LevelObject>>,
     mut commands: Commands,
 ) {
     let Some(SwitchToLevel(new_level_index)) = reader.read().last() else {
@@ -135,39 +135,6 @@ fn handle_level_switch(
         commands.entity(entity).despawn();
     }
     commands.run_system(switchable_levels.current().level);
-}
-
-#[derive(QueryData)]
-#[query_data(mutable)]
-struct PlayerQueryForPosition {
-    transform: &'static mut Transform,
-    avian3d_linear_velocity: Option<&'static mut LinearVelocity>,
-    avian3d_angular_velocity: Option<&'static mut AngularVelocity>,
-}
-
-fn handle_player_position(
-    time: Res<Time>,
-    mut player_query: Query<PlayerQueryForPosition, With<WaltzPlayer>>,
-    mut position_query: Query<(Entity, &mut PositionPlayer)>,
-    mut commands: Commands,
-) {
-    let Some((position_entity, mut position_player)) = position_query.iter_mut().next() else {
-        return;
-    };
-
-    for mut player in player_query.iter_mut() {
-        player.transform.translation = position_player.position;
-
-        if let Some(velocity) = player.avian3d_linear_velocity.as_mut() {
-            velocity.0 = Default::default()
-        }
-
-        if let Some(velocity) = player.avian3d_angular_velocity.as_mut() {
-            velocity.0 = Default::default()
-        }
-    }
-
-    if position_player.ttl.tick(time.delta()).is_finished() {
-        commands.entity(position_entity).despawn();
-    }
+    // the player starts the new level at its start, once the level placed it
+    player_respawn.restart();
 }
@@ -0,0 +1,161 @@
+//! Spawn points, checkpoints and the volumes that send the player back to them.
+//!
+//! The player starts a level at the [`SpawnPoint`] named [`LEVEL_START`]. Reaching a
+//! [`Checkpoint`] makes the spawn point it names the one the player comes back to, when it falls
+//! out of the level through an [`OutOfBounds`] volume or below a [`KillPlane`], and when it
+//! respawns after its death. The player is teleported once it exists and no level object is
+//! [`LevelLoading`], so a level may load before the player is set up.
+use avian3d::prelude::CollisionStart;
+use bevy::prelude::*;
+
+use super::LevelLoading;
+use crate::{
+    character::{DisableRagdoll, TeleportCharacter, WaltzPlayer},
+    gp::{Dead, Respawn},
+};
+
+/// The spawn point the player starts a level at, before it reaches a checkpoint.
+pub const LEVEL_START: &str = "start";
+
+pub(super) fn plugin(app: &mut App) {
+    app.init_resource::<PlayerRespawn>()
+        .add_observer(reach_checkpoint)
+        .add_observer(leave_bounds)
+        .add_observer(respawn_at_checkpoint)
+        .add_systems(Update, (check_kill_planes, respawn_player).chain());
+}
+
+/// A named place the player spawns at, with the rotation of its transform. Spawn points are
+/// placed at the root of the level, their transform is the world transform.
+#[derive(Component, Debug, Clone)]
+pub struct SpawnPoint(pub String);
+
+/// A sensor volume, the player respawns at the named spawn point once it passed through it.
+#[derive(Component, Debug, Clone)]
+pub struct Checkpoint(pub String);
+
+/// A sensor volume the player respawns from as soon as it enters it.
+#[derive(Component, Debug, Clone, Copy)]
+pub struct OutOfBounds;
+
+/// The player respawns as soon as it falls below this height.
+#[derive(Component, Debug, Clone, Copy)]
+pub struct KillPlane(pub f32);
+
+/// Where the player respawns and whether it is waiting to.
+#[derive(Resource, Debug)]
+pub struct PlayerRespawn {
+    spawn_point: String,
+    pending: bool,
+}
+
+impl Default for PlayerRespawn {
+    fn default() -> Self {
+        Self {
+            spawn_point: LEVEL_START.to_string(),
+            pending: false,
+        }
+    }
+}
+
+impl PlayerRespawn {
+    /// The spawn point of the last checkpoint reached, or the level start.
+    pub fn spawn_point(&self) -> &str {
+        &self.spawn_point
+    }
+
+    /// Sends the player to the start of a new level.
+    pub(super) fn restart(&mut self) {
+        self.spawn_point = LEVEL_START.to_string();
+        self.pending = true;
+    }
+}
+
+fn reach_checkpoint(
+    start: On<CollisionStart>,
+    checkpoints: Query<&Checkpoint>,
+    players: Query<(), With<WaltzPlayer>>,
+    mut respawn: ResMut<PlayerRespawn>,
+) {
+    let Ok(Checkpoint(spawn_point)) = checkpoints.get(start.collider1) else {
+        return;
+    };
+    if !start.body2.is_some_and(|body| players.contains(body)) {
+        return;
+    }
+
+    if respawn.spawn_point != *spawn_point {
+        info!("checkpoint reached, the player respawns at {spawn_point}");
+        respawn.spawn_point = spawn_point.clone();
+    }
+}
+
+fn leave_bounds(
+    start: On<CollisionStart>,
+    volumes: Query<(), With<OutOfBounds>>,
+    players: Query<(), (With<WaltzPlayer>, Without<Dead>)>,
+    mut respawn: ResMut<PlayerRespawn>,
+) {
+    if volumes.contains(start.collider1) && start.body2.is_some_and(|body| players.contains(body)) {
+        respawn.pending = true;
+    }
+}
+
+fn respawn_at_checkpoint(
+    respawned: On<Respawn>,
+    players: Query<(), With<WaltzPlayer>>,
+    mut respawn: ResMut<PlayerRespawn>,
+) {
+    if players.contains(respawned.entity) {
+        respawn.pending = true;
+    }
+}
+
+/// A dead player respawns after its death instead, where its ragdoll may still fall.
+fn check_kill_planes(
+    kill_planes: Query<&KillPlane>,
+    players: Query<&Transform, (With<WaltzPlayer>, Without<Dead>)>,
+    mut respawn: ResMut<PlayerRespawn>,
+) {
+    for transform in &players {
+        if kill_planes
+            .iter()
+            .any(|KillPlane(height)| transform.translation.y < *height)
+        {
+            respawn.pending = true;
+        }
+    }
+}
+
+pub(super) fn respawn_player(
+    mut respawn: ResMut<PlayerRespawn>,
+    players: Query<Entity, With<WaltzPlayer>>,
+    spawn_points: Query<(&SpawnPoint, &Transform)>,
+    loading: Query<(), With<LevelLoading>>,
+    mut commands: Commands,
+) {
+    if !respawn.pending || !loading.is_empty() {
+        return;
+    }
+    // the player is set up once its description loads
+    let Some(player) = players.iter().next() else {
+        return;
+    };
+    let Some((_, spawn_point)) = spawn_points
+        .iter()
+        .find(|(SpawnPoint(name), _)| *name == respawn.spawn_point)
+    else {
+        warn!("spawn point {} does not exist", respawn.spawn_point);
+        respawn.pending = false;
+        return;
+    };
+
+    respawn.pending = false;
+    // the ragdoll gets up where it lies first, the teleport takes it from there
+    commands.trigger(DisableRagdoll { entity: player });
+    commands.trigger(TeleportCharacter {
+        entity: player,
+        position: spawn_point.translation,
+        rotation: spawn_point.rotation,
+    });
+}
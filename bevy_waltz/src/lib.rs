@@ -11,15 +11,36 @@ use crate::{
 mod atmosphere;
 mod camera;
 mod character;
+mod config;
 mod control;
+mod gp;
 mod level_switch;
+mod lock_on;
 mod perf;
 mod utils;
-mod gp;
 
 use camera::WaltzCamera;
 use character::WaltzPlayer;
 
+pub use camera::{
+    CameraFocusTarget, CameraShakeSource, CrosshairTarget, FocusCamera, ReleaseCameraFocus,
+    ShakeCamera,
+};
+pub use character::{
+    CharacterLanded, DisableRagdoll, EnableRagdoll, EquippedWeapon, FireWeapon, Hit, ReloadWeapon,
+    TeleportCharacter, WeaponDelivery, WeaponFired, WeaponFiringPlugin, WeaponStats,
+};
+pub use gp::{
+    ActivatePower, Armor, Damage, DamageKind, DamagePlugin, Dead, Died, Energy, EquipGear, Gear,
+    Health, ModifierOp, Power, PowerActivated, PowerCooldowns, PowerId, PowerInfo, PowerPlugin,
+    PowerRegistry, PowerSlots, RegisterPowerExt, Respawn, RespawnAfter, StatKind, StatModifier,
+    Stats, UnequipGear,
+};
+pub use level_switch::{
+    Bumper, Checkpoint, KillPlane, LEVEL_START, OutOfBounds, PlayerRespawn, SpawnPoint,
+};
+pub use lock_on::{CycleLockOn, LockOn, Targetable, ToggleLockOn};
+
 pub struct WaltzPlugin;
 
 // No Tnua-related setup here - this is just normal Bevy (and Avian) stuff.
@@ -49,10 +70,15 @@ fn setup_level(
 impl Plugin for WaltzPlugin {
     fn build(&self, app: &mut App) {
         app.add_plugins(
-            LevelSwitchPlugin::new(Some("jungle_gym")).with("jungle_gym", jungle_gym::setup_level),
+            LevelSwitchPlugin::new(Some("jungle_gym"))
+                .with("jungle_gym", jungle_gym::setup_level)
+                .with_gltf("world", "waltz/scenes/World.glb"),
         );
         // app.add_systems(Startup, setup_level);
         app.add_plugins((WaltzCharacterPlugin, WaltzCameraPlugin, WaltzControlPlugin));
+        app.add_plugins(config::plugin);
+        app.add_plugins(lock_on::plugin);
+        app.add_plugins(gp::plugin);
         app.add_plugins(atmosphere::plugin);
         app.add_plugins(perf::plugin);
     }
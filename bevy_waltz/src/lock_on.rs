@@ -0,0 +1,270 @@
+//! Lock-on targeting: picks a [`Targetable`] from the camera view cone and keeps the camera
+//! framing both the player and the target while the character strafes around it.
+use avian3d::prelude::*;
+use bevy::prelude::*;
+
+use crate::{
+    WaltzCamera, WaltzPlayer,
+    camera::{CameraFocusTarget, LockOnCamera, ReleaseCameraLockOn, config::CollisionLayer},
+    gp::Dead,
+};
+
+/// Marks an entity the player can lock on to.
+#[derive(Component, Debug, Clone, Reflect)]
+#[reflect(Component)]
+pub struct Targetable {
+    /// offset from the entity origin to the point the camera and weapons aim at
+    pub aim_offset: Vec3,
+}
+
+impl Default for Targetable {
+    fn default() -> Self {
+        Self {
+            aim_offset: Vec3::Y,
+        }
+    }
+}
+
+/// The current lock-on target of the player.
+#[derive(Component, Debug, Default, Reflect)]
+#[reflect(Component)]
+pub struct LockOn {
+    pub target: Option<Entity>,
+}
+
+#[derive(Resource, Debug, Clone, Reflect)]
+#[reflect(Resource)]
+pub struct LockOnConfig {
+    /// max distance between the player and a target
+    pub max_distance: f32,
+    /// half angle in degrees of the cone in front of the camera to search targets in
+    pub view_cone: f32,
+    /// the lock is released once the target is this much further than `max_distance`
+    pub release_margin: f32,
+}
+
+impl Default for LockOnConfig {
+    fn default() -> Self {
+        Self {
+            max_distance: 25.0,
+            view_cone: 30.0,
+            release_margin: 5.0,
+        }
+    }
+}
+
+/// Locks on the best target in view, or releases the current one.
+#[derive(Event, Debug, Clone, Copy)]
+pub struct ToggleLockOn;
+
+/// Switches to the next target to the right (`1.0`) or to the left (`-1.0`) on the screen.
+#[derive(Event, Debug, Clone, Copy)]
+pub struct CycleLockOn(pub f32);
+
+pub(crate) fn plugin(app: &mut App) {
+    app.register_type::<Targetable>()
+        .register_type::<LockOn>()
+        .register_type::<LockOnConfig>()
+        .init_resource::<LockOnConfig>()
+        .add_observer(setup_lock_on)
+        .add_observer(toggle_lock_on)
+        .add_observer(cycle_lock_on)
+        .add_systems(Update, validate_lock_on);
+}
+
+fn setup_lock_on(trigger: On<Add, WaltzPlayer>, mut commands: Commands) {
+    commands.entity(trigger.entity).insert(LockOn::default());
+}
+
+struct Candidate {
+    entity: Entity,
+    aim_offset: Vec3,
+    /// horizontal position on the screen, negative on the left
+    screen_x: f32,
+    score: f32,
+}
+
+/// Collects the living targets in the camera view cone that are in range and in line of sight.
+fn collect_candidates(
+    camera: &GlobalTransform,
+    player: Vec3,
+    targets: &Query<(Entity, &GlobalTransform, &Targetable), Without<Dead>>,
+    parents: &Query<&ChildOf>,
+    spatial_query: &SpatialQuery,
+    config: &LockOnConfig,
+) -> Vec<Candidate> {
+    let view_cone = config.view_cone.to_radians();
+    let origin = camera.translation();
+    let forward = camera.forward();
+    let right = camera.right();
+
+    targets
+        .iter()
+        .filter_map(|(entity, transform, targetable)| {
+            let point = transform.translation() + targetable.aim_offset;
+            let distance = point.distance(player);
+            if distance > config.max_distance {
+                return None;
+            }
+
+            let to_target = point - origin;
+            let angle = forward.angle_between(to_target);
+            if angle > view_cone {
+                return None;
+            }
+
+            let (direction, length) = Dir3::new_and_length(to_target).ok()?;
+            // only the level hides a target, the colliders of the target itself do not
+            let filter = SpatialQueryFilter::from_mask([
+                CollisionLayer::Terrain,
+                CollisionLayer::CameraObstacle,
+            ]);
+            if let Some(hit) = spatial_query.cast_ray(origin, direction, length, true, &filter) {
+                let hit_target = hit.entity == entity
+                    || parents
+                        .iter_ancestors(hit.entity)
+                        .any(|ancestor| ancestor == entity);
+                if !hit_target {
+                    return None;
+                }
+            }
+
+            Some(Candidate {
+                entity,
+                aim_offset: targetable.aim_offset,
+                screen_x: direction.dot(*right),
+                score: angle / view_cone + distance / config.max_distance,
+            })
+        })
+        .collect()
+}
+
+/// The camera frames the aim point of the target, the one the candidates are picked by.
+fn lock(commands: &mut Commands, lock_on: &mut LockOn, target: Entity, aim_offset: Vec3) {
+    debug!("lock on {target}");
+    lock_on.target = Some(target);
+    commands.trigger(LockOnCamera(CameraFocusTarget::EntityOffset(
+        target, aim_offset,
+    )));
+}
+
+fn release(commands: &mut Commands, lock_on: &mut LockOn) {
+    if lock_on.target.take().is_some() {
+        debug!("release lock on");
+        commands.trigger(ReleaseCameraLockOn);
+    }
+}
+
+fn toggle_lock_on(
+    _toggle: On<ToggleLockOn>,
+    mut commands: Commands,
+    player: Single<(&Transform, &mut LockOn), With<WaltzPlayer>>,
+    camera: Single<&GlobalTransform, With<WaltzCamera>>,
+    targets: Query<(Entity, &GlobalTransform, &Targetable), Without<Dead>>,
+    parents: Query<&ChildOf>,
+    spatial_query: SpatialQuery,
+    config: Res<LockOnConfig>,
+) {
+    let (player_transform, mut lock_on) = player.into_inner();
+
+    if lock_on.target.is_some() {
+        release(&mut commands, &mut lock_on);
+        return;
+    }
+
+    let best = collect_candidates(
+        &camera,
+        player_transform.translation,
+        &targets,
+        &parents,
+        &spatial_query,
+        &config,
+    )
+    .into_iter()
+    .min_by(|a, b| a.score.total_cmp(&b.score));
+
+    if let Some(candidate) = best {
+        lock(
+            &mut commands,
+            &mut lock_on,
+            candidate.entity,
+            candidate.aim_offset,
+        );
+    }
+}
+
+fn cycle_lock_on(
+    cycle: On<CycleLockOn>,
+    mut commands: Commands,
+    player: Single<(&Transform, &mut LockOn), With<WaltzPlayer>>,
+    camera: Single<&GlobalTransform, With<WaltzCamera>>,
+    targets: Query<(Entity, &GlobalTransform, &Targetable), Without<Dead>>,
+    parents: Query<&ChildOf>,
+    spatial_query: SpatialQuery,
+    config: Res<LockOnConfig>,
+) {
+    let (player_transform, mut lock_on) = player.into_inner();
+    let Some(current) = lock_on.target else {
+        return;
+    };
+
+    let candidates = collect_candidates(
+        &camera,
+        player_transform.translation,
+        &targets,
+        &parents,
+        &spatial_query,
+        &config,
+    );
+
+    // the current target may be just outside of the view cone, fall back to the screen center
+    let current_x = candidates
+        .iter()
+        .find(|candidate| candidate.entity == current)
+        .map(|candidate| candidate.screen_x)
+        .unwrap_or_default();
+
+    let side = cycle.0.signum();
+    let next = candidates
+        .iter()
+        .filter(|candidate| candidate.entity != current)
+        .filter(|candidate| (candidate.screen_x - current_x) * side > 0.0)
+        .min_by(|a, b| {
+            (a.screen_x - current_x)
+                .abs()
+                .total_cmp(&(b.screen_x - current_x).abs())
+        });
+
+    if let Some(candidate) = next {
+        lock(
+            &mut commands,
+            &mut lock_on,
+            candidate.entity,
+            candidate.aim_offset,
+        );
+    }
+}
+
+/// Releases the lock once the target is gone, dead or out of range.
+fn validate_lock_on(
+    mut commands: Commands,
+    player: Single<(&Transform, &mut LockOn), With<WaltzPlayer>>,
+    targets: Query<&GlobalTransform, (With<Targetable>, Without<Dead>)>,
+    config: Res<LockOnConfig>,
+) {
+    let (player_transform, mut lock_on) = player.into_inner();
+    let Some(target) = lock_on.target else {
+        return;
+    };
+
+    let in_range = targets.get(target).is_ok_and(|transform| {
+        transform
+            .translation()
+            .distance(player_transform.translation)
+            <= config.max_distance + config.release_margin
+    });
+
+    if !in_range {
+        release(&mut commands, &mut lock_on);
+    }
+}